@@ -1,12 +1,10 @@
 use std::path::Path;
 
 use axum::{http::Uri, routing::get, Router};
-use fairy_http::config::ViteConfigExt;
-use fairy_http::{config::RouteMap, Template};
-use fairy_render::reggie::{Body, HttpClient, HttpClientFactory};
+use fairy_http::{FairyService, InternalClient, Template};
 use fairy_vite::ViteConfig;
-use fairy_vite::{AssetKind, FairyResult, ViteError};
-use futures::future::BoxFuture;
+use fairy_vite::{AssetKind, AssetRole, FairyResult, ViteError};
+use reggie::Reqwest;
 
 markup::define! {
     Home(req: FairyResult) {
@@ -19,9 +17,15 @@ markup::define! {
                     "#main { padding: 2rem; }"
                 }
                 @for file in &req.assets {
-                    @match file.kind {
-                        AssetKind::Styling => {
-                            link[href= format!("{}", &file.file), rel="stylesheet", class="en klasse"] {  }
+                    @match (file.role, file.kind) {
+                        (AssetRole::Stylesheet, _) => {
+                            link[href= &file.file, rel="stylesheet", integrity=&file.integrity, crossorigin=file.crossorigin.map(|c| c.as_str())] {  }
+                        }
+                        (AssetRole::Preload, AssetKind::Script) => {
+                            link[href= &file.file, rel="modulepreload", integrity=&file.integrity, crossorigin=file.crossorigin.map(|c| c.as_str())] {  }
+                        }
+                        (AssetRole::Preload, _) => {
+                            link[href= &file.file, rel="preload", "as"=file.as_type(), integrity=&file.integrity, crossorigin=file.crossorigin.map(|c| c.as_str())] {  }
                         }
                         _ => {
                             ""
@@ -41,9 +45,9 @@ markup::define! {
                     }
                 }
                 @for file in &req.assets {
-                    @match file.kind {
-                        AssetKind::Script => {
-                            script[src= format!("{}", &file.file), type="module"] {  }
+                    @match (file.role, file.kind) {
+                        (AssetRole::Entry, AssetKind::Script) => {
+                            script[src= &file.file, type="module", integrity=&file.integrity, crossorigin=file.crossorigin.map(|c| c.as_str())] {  }
                         }
                         _ => {
                             ""
@@ -113,74 +117,26 @@ async fn main() {
     //     .with_max_level(LevelFilter::TRACE)
     //     .init();
 
-    let mut app = Router::new();
+    let api = Router::new().route("/api/message", get(api));
 
     let solid_config = ViteConfig::load(Path::new("solid-config.json"))
         .await
         .unwrap();
 
-    let fetcher = Fetcher {
-        client: reqwest::Client::new(),
-    };
+    let fetcher = InternalClient::new(api.clone())
+        .origin("localhost:3000")
+        .external(reggie::factory_arc(Reqwest::default()));
 
-    let solid = solid_config
-        .build(fetcher.clone(), T, RouteMap::default())
+    let solid = FairyService::builder(solid_config)
+        .http(fetcher)
+        .template(T)
+        .bundle_routes()
+        .build()
         .await
         .unwrap();
 
-    app = app.route("/api/message", get(api)).fallback_service(solid);
+    let app = api.fallback_service(solid);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
     axum::serve(listener, app).await.unwrap();
 }
-
-#[derive(Debug, Clone)]
-pub struct Fetcher {
-    client: reqwest::Client,
-}
-
-impl HttpClientFactory for Fetcher {
-    fn create<B>(&self) -> Self::Client<B>
-    where
-        B: fairy_render::reggie::http_body::Body + Send + 'static,
-        B::Data: Into<axum::body::Bytes> + Send,
-        B::Error: Into<fairy_render::reggie::Error> + Send,
-    {
-        self.clone()
-    }
-
-    type Client<B> = Self
-    where
-        B: fairy_render::reggie::http_body::Body + Send + 'static,
-        B::Data: Into<axum::body::Bytes> + Send,
-        B::Error: Into<fairy_render::reggie::Error> + Send;
-}
-
-impl<B> HttpClient<B> for Fetcher
-where
-    B: fairy_render::reggie::http_body::Body + Send + 'static,
-    B::Data: Into<axum::body::Bytes> + Send,
-    B::Error: Into<fairy_render::reggie::Error> + Send,
-{
-    type Body = Body;
-
-    type Future<'a> =
-        BoxFuture<'a, Result<axum::http::Response<Self::Body>, fairy_render::reggie::Error>>;
-
-    fn send<'a>(
-        &'a self,
-        request: axum::http::Request<B>,
-    ) -> BoxFuture<'a, Result<axum::http::Response<Self::Body>, fairy_render::reggie::Error>> {
-        Box::pin(async move {
-            if request.uri().scheme_str() == Some("internal") {
-                return Ok(fairy_render::reggie::http::Response::builder()
-                    .body(Body::from(String::from("Hello, World!")))
-                    .unwrap());
-            }
-
-            let resp = self.client.send(request).await?;
-
-            Ok(resp.map(|m| Body::from_streaming(m)))
-        })
-    }
-}
@@ -0,0 +1,609 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use axum::{
+    http::{Method, StatusCode},
+    routing::{any_service, on_service, MethodFilter},
+    Router,
+};
+use fairy_render::{quick::QuickFactory, FetchCache, FetchPolicy, RenderMetrics};
+use fairy_vite::{Fairy, ServeMode, ViteConfig, ViteError};
+use reggie::{Body, HttpClient, HttpClientFactory, SharedClientFactory};
+use tower_http::services::ServeDir;
+
+use crate::{
+    csrf::Csrf,
+    render::{FairyRenderService, DEFAULT_BODY_LIMIT},
+    template::{DefaultTemplate, ErrorHandler, Template},
+    ViteProxy, ViteProxyLayer,
+};
+
+/// Entry point for serving a vite app, see [`FairyService::builder`]
+pub struct FairyService;
+
+impl FairyService {
+    /// Starts building a service for `config`.
+    ///
+    /// ```ignore
+    /// let router = FairyService::builder(config)
+    ///     .http(client)
+    ///     .template(MyTemplate)
+    ///     .route("home", "/")
+    ///     .route("home", "/users/:id")
+    ///     .route_on("admin", "/admin/*rest", [Method::GET])
+    ///     .build()
+    ///     .await?;
+    /// ```
+    pub fn builder(config: ViteConfig) -> FairyServiceBuilder {
+        FairyServiceBuilder {
+            config,
+            http: None,
+            template: Arc::new(DefaultTemplate),
+            error_handler: None,
+            routes: Vec::new(),
+            bundle_routes: None,
+            mount_assets: true,
+            mode: None,
+            quick: QuickFactory::default(),
+            csrf: None,
+            metrics: None,
+            server_timing: false,
+            strict: true,
+            body_limit: DEFAULT_BODY_LIMIT,
+        }
+    }
+}
+
+pub struct FairyServiceBuilder {
+    config: ViteConfig,
+    http: Option<SharedClientFactory>,
+    template: Arc<dyn Template + Send + Sync>,
+    error_handler: Option<Arc<dyn ErrorHandler + Send + Sync>>,
+    routes: Vec<Route>,
+    /// Entry whose server bundle exports the routes, `Some(None)` for the default entry
+    bundle_routes: Option<Option<String>>,
+    mount_assets: bool,
+    mode: Option<ServeMode>,
+    quick: QuickFactory,
+    csrf: Option<Csrf>,
+    metrics: Option<Arc<dyn RenderMetrics>>,
+    server_timing: bool,
+    strict: bool,
+    body_limit: usize,
+}
+
+impl FairyServiceBuilder {
+    /// Client used for fetches made while rendering, and for loading modules in dev ssr.
+    /// Required unless running in [`ServeMode::Dev`]
+    pub fn http<H>(mut self, http: H) -> Self
+    where
+        H: HttpClientFactory + Send + Sync + 'static,
+        H::Client<Body>: Send + Sync + 'static,
+        for<'b> <H::Client<Body> as HttpClient<Body>>::Future<'b>: Send,
+        <H::Client<Body> as HttpClient<Body>>::Body: Into<reggie::Body>,
+    {
+        self.http = Some(reggie::factory_arc(http));
+        self
+    }
+
+    /// Template wrapping rendered content in a document. Defaults to [`DefaultTemplate`]
+    pub fn template<T: Template + Send + Sync + 'static>(mut self, template: T) -> Self {
+        self.template = Arc::new(template);
+        self
+    }
+
+    /// Respond to failed renders with `handler`, eg. to pick a status or error page.
+    /// By default the template renders the error with a `500 Internal Server Error`
+    ///
+    /// ```ignore
+    /// builder.on_error(|_uri: &Uri, err: &ViteError| match err {
+    ///     ViteError::EntryNotFound(_) => StatusCode::NOT_FOUND.into_response(),
+    ///     _ => (StatusCode::INTERNAL_SERVER_ERROR, Html(ERROR_PAGE)).into_response(),
+    /// })
+    /// ```
+    pub fn on_error<H: ErrorHandler + Send + Sync + 'static>(mut self, handler: H) -> Self {
+        self.error_handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Serve the entry `entry` at `path`. Without routes, the default entry handles every request.
+    ///
+    /// Paths use axum's syntax, so `/users/:id` and `/files/*rest` match parameters and
+    /// wildcards. Matched parameters are passed to the server bundle as `params` in the
+    /// render context. An entry can be served at any number of paths
+    pub fn route(mut self, entry: impl Into<String>, path: impl Into<String>) -> Self {
+        self.routes.push(Route {
+            entry: entry.into(),
+            path: path.into(),
+            methods: None,
+        });
+        self
+    }
+
+    /// Like [`FairyServiceBuilder::route`], but only for requests with one of `methods`.
+    /// Different entries can share a path as long as their methods don't overlap,
+    /// other methods are answered with `405 Method Not Allowed`
+    pub fn route_on(
+        mut self,
+        entry: impl Into<String>,
+        path: impl Into<String>,
+        methods: impl IntoIterator<Item = Method>,
+    ) -> Self {
+        self.routes.push(Route {
+            entry: entry.into(),
+            path: path.into(),
+            methods: Some(methods.into_iter().collect()),
+        });
+        self
+    }
+
+    /// Register the paths exported as `routes` by the server bundle of the default entry,
+    /// eg. `export const routes = ["/", "/users/:id"]` or solid style route definitions.
+    ///
+    /// Other paths get a `404 Not Found`, rendered by the default entry so the app can
+    /// show its own not found page. Catch-all routes like `*404` are left to this fallback.
+    /// Has no effect without a server bundle, ie. in [`ServeMode::Dev`].
+    /// Configs with several entries must name one, see [`FairyServiceBuilder::bundle_routes_of`]
+    pub fn bundle_routes(mut self) -> Self {
+        self.bundle_routes = Some(None);
+        self
+    }
+
+    /// Like [`FairyServiceBuilder::bundle_routes`], with the routes and the 404 page of `entry`
+    pub fn bundle_routes_of(mut self, entry: impl Into<String>) -> Self {
+        self.bundle_routes = Some(Some(entry.into()));
+        self
+    }
+
+    /// Serve the assets directory in production, and in dev mode proxy to the vite dev server
+    /// when [`DevServer::proxy`](fairy_vite::DevServer::proxy) is set. Enabled by default
+    pub fn mount_assets(mut self, mount: bool) -> Self {
+        self.mount_assets = mount;
+        self
+    }
+
+    /// Overrides [`ViteConfig::serve_mode`]
+    pub fn mode(mut self, mode: ServeMode) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    pub fn dev(self) -> Self {
+        self.mode(ServeMode::Dev)
+    }
+
+    /// Configure the vm, eg. isolation or determinism
+    pub fn quick(mut self, factory: QuickFactory) -> Self {
+        self.quick = factory;
+        self
+    }
+
+    /// Deduplicate and cache fetches made while rendering
+    pub fn fetch_cache(mut self, cache: FetchCache) -> Self {
+        self.quick = self.quick.fetch_cache(cache);
+        self
+    }
+
+    /// Restrict fetches made while rendering, see [`FetchPolicy`]
+    pub fn fetch_policy(mut self, policy: FetchPolicy) -> Self {
+        self.quick = self.quick.fetch_policy(policy);
+        self
+    }
+
+    /// Require a valid csrf token on actions
+    pub fn csrf(mut self, csrf: Csrf) -> Self {
+        self.csrf = Some(csrf);
+        self
+    }
+
+    /// Maximum size of action bodies in bytes, larger bodies are answered with
+    /// `413 Payload Too Large`. Defaults to 2MB
+    pub fn body_limit(mut self, limit: usize) -> Self {
+        self.body_limit = limit;
+        self
+    }
+
+    pub fn metrics<M: RenderMetrics + 'static>(mut self, metrics: Arc<M>) -> Self {
+        self.quick = self.quick.metrics(metrics.clone());
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Emit `Server-Timing` headers, see [`FairyRenderService::server_timing`]
+    pub fn server_timing(mut self, enabled: bool) -> Self {
+        self.server_timing = enabled;
+        self
+    }
+
+    /// Fail to build when validating the build output finds problems,
+    /// otherwise they are logged. Enabled by default
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Creates the renderer and returns a router serving the configured routes.
+    /// Fails with [`ViteError::InvalidRoute`] for malformed paths, or when routes overlap,
+    /// and with [`ViteError::InvalidCsrf`] for unusable csrf names
+    pub async fn build(self) -> Result<Router, ViteError> {
+        if let Some(csrf) = &self.csrf {
+            csrf.check().map_err(ViteError::InvalidCsrf)?;
+        }
+
+        let mode = self.mode.unwrap_or_else(|| self.config.serve_mode());
+
+        let mut config = self.config.clone();
+        // Without the proxy layer, dev assets must be loaded from the dev server directly
+        config.dev_server.proxy &= self.mount_assets;
+
+        let fairy = match (mode, &self.http) {
+            (ServeMode::Dev, _) => Fairy::dev(config)?,
+            (_, Some(http)) => {
+                Fairy::with_client(config, http.clone(), self.quick.clone(), mode).await?
+            }
+            (_, None) => return Err(ViteError::MissingHttpClient),
+        };
+
+        let report = fairy.validate().await;
+        if self.strict {
+            report.into_result()?;
+        } else if !report.is_ok() {
+            tracing::warn!("{report}");
+        }
+
+        let mut router = Router::new();
+        let mut routed = RouteTable::default();
+
+        for route in &self.routes {
+            routed.insert(&route.path, route.methods.as_deref())?;
+
+            let service = self.render_service(&fairy, Some(&route.entry))?;
+
+            router = router.route(
+                &route.path,
+                match &route.methods {
+                    Some(methods) => on_service(method_filter(&route.path, methods)?, service),
+                    None => any_service(service),
+                },
+            );
+        }
+
+        let bundle_routes = match &self.bundle_routes {
+            Some(entry) => fairy
+                .routes(entry.as_deref())
+                .await?
+                .map(|paths| (entry.as_deref(), paths)),
+            None => None,
+        };
+
+        if let Some((entry, paths)) = bundle_routes {
+            let service = self.render_service(&fairy, entry)?;
+
+            for path in bundle_patterns(&mut routed, &paths)? {
+                router = router.route(&path, any_service(service.clone()));
+            }
+
+            let not_found = service.status(StatusCode::NOT_FOUND);
+            router = router.fallback_service(
+                on_service(MethodFilter::GET.or(MethodFilter::HEAD), not_found)
+                    .fallback(|| async { StatusCode::NOT_FOUND }),
+            );
+        } else if self.routes.is_empty() {
+            router = router.fallback_service(self.render_service(&fairy, None)?);
+        }
+
+        if !self.mount_assets {
+            return Ok(router);
+        }
+
+        if !fairy.is_dev() {
+            router = router.nest_service(
+                &fairy.config().assets_mount(),
+                ServeDir::new(fairy.config().assets()),
+            );
+        } else if fairy.config().dev_server.proxy {
+            let origin = fairy.config().dev_origin();
+            let proxy = ViteProxy::new(&origin).map_err(|_| ViteError::DevOrigin(origin))?;
+            router = router.layer(ViteProxyLayer::new(proxy));
+        }
+
+        Ok(router)
+    }
+
+    fn render_service(
+        &self,
+        fairy: &Fairy,
+        entry: Option<&str>,
+    ) -> Result<FairyRenderService, ViteError> {
+        let mut service =
+            FairyRenderService::new(fairy.create_renderer(entry)?, self.template.clone())
+                .server_timing(self.server_timing)
+                .body_limit(self.body_limit);
+
+        if let Some(csrf) = &self.csrf {
+            service = service.csrf(csrf.clone());
+        }
+
+        if let Some(metrics) = &self.metrics {
+            service = service.metrics(metrics.clone());
+        }
+
+        if let Some(handler) = &self.error_handler {
+            service = service.on_error(handler.clone());
+        }
+
+        Ok(service)
+    }
+}
+
+struct Route {
+    entry: String,
+    path: String,
+    methods: Option<Vec<Method>>,
+}
+
+/// Registered paths, checked up front since axum panics on conflicting routes
+#[derive(Default)]
+struct RouteTable {
+    /// Registered paths by their shape, see [`path_shape`]
+    paths: HashMap<String, Registered>,
+}
+
+struct Registered {
+    path: String,
+    /// Whether a route accepts any method, used for methods without their own route
+    any: bool,
+    methods: HashSet<Method>,
+}
+
+impl RouteTable {
+    fn contains(&self, path: &str) -> bool {
+        path_shape(path).is_ok_and(|shape| self.paths.contains_key(&shape))
+    }
+
+    /// Whether `path` is registered for some methods only, so a route for any method can be
+    /// merged into it to handle the others
+    fn is_method_only(&self, path: &str) -> bool {
+        path_shape(path)
+            .ok()
+            .and_then(|shape| self.paths.get(&shape))
+            .is_some_and(|registered| registered.path == path && !registered.any)
+    }
+
+    /// Registers `path` for `methods`, or any method when `None`
+    fn insert(&mut self, path: &str, methods: Option<&[Method]>) -> Result<(), ViteError> {
+        let invalid = |reason: &str| ViteError::InvalidRoute(format!("{path}: {reason}"));
+
+        let shape = path_shape(path).map_err(invalid)?;
+
+        let registered = self.paths.entry(shape).or_insert_with(|| Registered {
+            path: path.to_string(),
+            any: false,
+            methods: HashSet::new(),
+        });
+
+        if registered.path != path {
+            return Err(invalid(&format!("conflicts with {}", registered.path)));
+        }
+
+        let Some(methods) = methods else {
+            if registered.any {
+                return Err(invalid("already routed"));
+            }
+            registered.any = true;
+            return Ok(());
+        };
+
+        if methods.is_empty() {
+            return Err(invalid("no methods given"));
+        }
+
+        for method in methods {
+            if !registered.methods.insert(method.clone()) {
+                return Err(invalid(&format!("{method} is already routed")));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Validates `path` and returns it with parameter names removed, so paths which axum
+/// would consider the same route compare equal
+fn path_shape(path: &str) -> Result<String, &'static str> {
+    let Some(rest) = path.strip_prefix('/') else {
+        return Err("paths must start with a slash");
+    };
+
+    let segments = rest.split('/').collect::<Vec<_>>();
+    let mut shape = String::new();
+
+    for (idx, segment) in segments.iter().enumerate() {
+        shape.push('/');
+
+        if let Some(name) = segment.strip_prefix(':') {
+            if name.is_empty() {
+                return Err("parameters must be named");
+            }
+            shape.push(':');
+        } else if let Some(name) = segment.strip_prefix('*') {
+            if name.is_empty() {
+                return Err("wildcards must be named");
+            }
+            if idx + 1 != segments.len() {
+                return Err("wildcards must be the last segment");
+            }
+            shape.push('*');
+        } else if segment.contains([':', '*']) {
+            return Err("parameters must span a whole segment");
+        } else {
+            shape.push_str(segment);
+        }
+    }
+
+    Ok(shape)
+}
+
+fn method_filter(path: &str, methods: &[Method]) -> Result<MethodFilter, ViteError> {
+    methods
+        .iter()
+        .map(|method| {
+            MethodFilter::try_from(method.clone()).map_err(|_| {
+                ViteError::InvalidRoute(format!("{path}: unsupported method {method}"))
+            })
+        })
+        .reduce(|acc, filter| Ok(acc?.or(filter?)))
+        .unwrap_or_else(|| Err(ViteError::InvalidRoute(format!("{path}: no methods given"))))
+}
+
+/// Registers the patterns of the bundle's `paths` in `routed` and returns them.
+/// Explicit routes take precedence, so patterns already routed are skipped unless the
+/// explicit route only handles some methods. Then the bundle handles the remaining ones
+fn bundle_patterns(routed: &mut RouteTable, paths: &[String]) -> Result<Vec<String>, ViteError> {
+    let mut patterns = Vec::new();
+
+    for pattern in paths.iter().flat_map(|path| route_patterns(path)) {
+        if routed.contains(&pattern) && !routed.is_method_only(&pattern) {
+            continue;
+        }
+
+        routed.insert(&pattern, None)?;
+        patterns.push(pattern);
+    }
+
+    Ok(patterns)
+}
+
+/// Translates a route path of the server bundle to axum's syntax, expanding optional
+/// parameters (`/:lang?/about`). Patterns catching everything from the root, like `*404`,
+/// are skipped as they are served by the fallback
+fn route_patterns(path: &str) -> Vec<String> {
+    let mut patterns = vec![String::new()];
+
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        if let Some(name) = segment.strip_prefix('*') {
+            let name = if name.is_empty() { "rest" } else { name };
+            for pattern in &mut patterns {
+                pattern.push_str(&format!("/*{name}"));
+            }
+            // Wildcards must be last
+            break;
+        }
+
+        if let Some(param) = segment.strip_suffix('?') {
+            let with = patterns
+                .iter()
+                .map(|pattern| format!("{pattern}/{param}"))
+                .collect::<Vec<_>>();
+            patterns.extend(with);
+        } else {
+            for pattern in &mut patterns {
+                pattern.push('/');
+                pattern.push_str(segment);
+            }
+        }
+    }
+
+    patterns
+        .into_iter()
+        .filter(|pattern| !pattern.starts_with("/*"))
+        .map(|pattern| {
+            if pattern.is_empty() {
+                "/".to_string()
+            } else {
+                pattern
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn route_table_rejects_conflicts() {
+        let mut table = RouteTable::default();
+
+        table.insert("/users/:id", Some(&[Method::GET])).unwrap();
+        table.insert("/users/:id", Some(&[Method::POST])).unwrap();
+        table.insert("/users/:id", None).unwrap();
+        table.insert("/users/new", None).unwrap();
+
+        assert!(table.insert("/users/:id", Some(&[Method::GET])).is_err());
+        assert!(table.insert("/users/:id", None).is_err());
+        assert!(table.insert("/users/:name", Some(&[Method::PUT])).is_err());
+        assert!(table.contains("/users/:name"));
+        assert!(!table.contains("/posts/:id"));
+    }
+
+    #[test]
+    fn route_table_rejects_malformed_paths() {
+        let mut table = RouteTable::default();
+
+        for path in ["users", "/users/:", "/files/*", "/*rest/edit", "/a:b"] {
+            assert!(
+                matches!(table.insert(path, None), Err(ViteError::InvalidRoute(_))),
+                "{path}"
+            );
+        }
+
+        assert!(table.insert("/users/:id", Some(&[])).is_err());
+        assert!(method_filter("/", &[Method::from_bytes(b"PURGE").unwrap()]).is_err());
+    }
+
+    #[test]
+    fn route_patterns_expand_bundle_paths() {
+        assert_eq!(route_patterns(""), ["/"]);
+        assert_eq!(route_patterns("/"), ["/"]);
+        assert_eq!(route_patterns("/users/:id"), ["/users/:id"]);
+        assert_eq!(route_patterns("/:lang?/about"), ["/about", "/:lang/about"]);
+        assert_eq!(route_patterns("/:a?/:b?"), ["/", "/:a", "/:b", "/:a/:b"]);
+        assert_eq!(route_patterns("/docs/*"), ["/docs/*rest"]);
+        assert_eq!(route_patterns("/docs/*path/ignored"), ["/docs/*path"]);
+    }
+
+    #[test]
+    fn route_patterns_skip_root_catch_alls() {
+        assert!(route_patterns("*").is_empty());
+        assert!(route_patterns("/*404").is_empty());
+        assert_eq!(route_patterns("/:lang?/*rest"), ["/:lang/*rest"]);
+    }
+
+    #[test]
+    fn bundle_patterns_fill_in_methods() {
+        let mut table = RouteTable::default();
+        table.insert("/users/:id", Some(&[Method::POST])).unwrap();
+        table.insert("/posts/:id", None).unwrap();
+
+        let paths = ["/users/:id", "/posts/:id"].map(String::from);
+
+        assert_eq!(bundle_patterns(&mut table, &paths).unwrap(), ["/users/:id"]);
+        // Both explicit and bundle routes are registered now
+        assert!(!table.is_method_only("/users/:id"));
+        assert!(table.insert("/users/:id", Some(&[Method::POST])).is_err());
+    }
+
+    #[test]
+    fn bundle_patterns_skip_duplicates() {
+        let mut table = RouteTable::default();
+        table.insert("/users/:id", Some(&[Method::POST])).unwrap();
+
+        let paths = [
+            "/",
+            "/:lang?",
+            "/:a?/:b?",
+            "/users/:name",
+            "/about",
+            "/about",
+        ]
+        .map(String::from);
+
+        assert_eq!(
+            bundle_patterns(&mut table, &paths).unwrap(),
+            ["/", "/:lang", "/:a/:b", "/about"]
+        );
+    }
+}
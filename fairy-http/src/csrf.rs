@@ -0,0 +1,130 @@
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue};
+use fairy_render::ActionPayload;
+
+/// Double submit cookie protection for actions.
+///
+/// A token is stored in a cookie and must be echoed back, either through a
+/// header or a form field, on every non-GET request. The token is forwarded
+/// to the server bundle through the request header, so SSR code can render it
+/// into forms.
+#[derive(Debug, Clone)]
+pub struct Csrf {
+    cookie: String,
+    field: String,
+    header: String,
+}
+
+impl Default for Csrf {
+    fn default() -> Self {
+        Csrf {
+            cookie: "fairy-csrf".to_string(),
+            field: "_csrf".to_string(),
+            header: "x-csrf-token".to_string(),
+        }
+    }
+}
+
+impl Csrf {
+    pub fn cookie(mut self, name: impl Into<String>) -> Self {
+        self.cookie = name.into();
+        self
+    }
+
+    pub fn field(mut self, name: impl Into<String>) -> Self {
+        self.field = name.into();
+        self
+    }
+
+    pub fn header(mut self, name: impl Into<String>) -> Self {
+        self.header = name.into();
+        self
+    }
+
+    pub fn header_name(&self) -> &str {
+        &self.header
+    }
+
+    /// Returns the token stored in the request cookies
+    pub fn token(&self, headers: &HeaderMap) -> Option<String> {
+        headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(name, _)| *name == self.cookie)
+            .map(|(_, value)| value.to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Checks the submitted token against the cookie
+    pub fn validate(&self, headers: &HeaderMap, payload: &ActionPayload) -> bool {
+        let Some(expected) = self.token(headers) else {
+            return false;
+        };
+
+        let submitted = headers
+            .get(&self.header)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+            .or_else(|| match payload {
+                ActionPayload::Form(fields) => fields
+                    .iter()
+                    .find(|(name, _)| *name == self.field)
+                    .map(|(_, value)| value.clone()),
+                _ => None,
+            });
+
+        match submitted {
+            Some(submitted) => constant_time_eq(expected.as_bytes(), submitted.as_bytes()),
+            None => false,
+        }
+    }
+
+    /// Creates a new random token
+    pub fn generate() -> String {
+        let mut buf = [0u8; 32];
+        getrandom::getrandom(&mut buf).expect("random source");
+        buf.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /// Checks that the configured names can be used in headers and forms
+    pub fn check(&self) -> Result<(), String> {
+        let is_token = |name: &str| {
+            !name.is_empty()
+                && name
+                    .bytes()
+                    .all(|b| b.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?={}".contains(&b))
+        };
+
+        if !is_token(&self.cookie) {
+            return Err(format!("invalid cookie name: {:?}", self.cookie));
+        }
+
+        if HeaderName::from_bytes(self.header.as_bytes()).is_err() {
+            return Err(format!("invalid header name: {:?}", self.header));
+        }
+
+        if self.field.is_empty() {
+            return Err("the form field name is empty".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// The `Set-Cookie` value storing `token`, `None` if the cookie name is invalid
+    pub(crate) fn set_cookie(&self, token: &str) -> Option<HeaderValue> {
+        HeaderValue::from_str(&format!(
+            "{}={}; Path=/; HttpOnly; SameSite=Lax",
+            self.cookie, token
+        ))
+        .ok()
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
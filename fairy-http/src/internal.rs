@@ -0,0 +1,172 @@
+use std::{convert::Infallible, sync::Arc};
+
+use axum::http::{header, HeaderName, Uri};
+use fairy_render::request_headers;
+use futures::future::BoxFuture;
+use reggie::{
+    bytes::Bytes,
+    http::{Request, Response},
+    http_body::Body as HttpBody,
+    http_body_util::BodyExt,
+    Body, HttpClient, HttpClientFactory, SharedClientFactory,
+};
+use tower::{Service, ServiceExt};
+
+pub use fairy_render::with_request_headers;
+
+/// Http client which dispatches `internal://` and same-origin requests
+/// straight into a tower service, like an axum `Router`, without touching the network.
+///
+/// Everything else is handed to the external client, if one is configured.
+pub struct InternalClient<S> {
+    service: S,
+    origins: Arc<Vec<String>>,
+    forward: Arc<Vec<HeaderName>>,
+    external: Option<SharedClientFactory>,
+}
+
+impl<S: Clone> Clone for InternalClient<S> {
+    fn clone(&self) -> Self {
+        InternalClient {
+            service: self.service.clone(),
+            origins: self.origins.clone(),
+            forward: self.forward.clone(),
+            external: self.external.clone(),
+        }
+    }
+}
+
+impl<S> InternalClient<S> {
+    pub fn new(service: S) -> InternalClient<S> {
+        InternalClient {
+            service,
+            origins: Arc::default(),
+            forward: Arc::new(vec![header::COOKIE, header::AUTHORIZATION]),
+            external: None,
+        }
+    }
+
+    /// Treat requests to this host (eg. `example.com` or `localhost:3000`) as internal
+    pub fn origin(mut self, host: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.origins).push(host.into());
+        self
+    }
+
+    /// Headers copied from the incoming request. Defaults to `cookie` and `authorization`
+    pub fn forward(mut self, headers: impl IntoIterator<Item = HeaderName>) -> Self {
+        self.forward = Arc::new(headers.into_iter().collect());
+        self
+    }
+
+    /// Client used for all requests which are not internal
+    pub fn external(mut self, client: SharedClientFactory) -> Self {
+        self.external = Some(client);
+        self
+    }
+
+    fn is_internal(&self, uri: &Uri) -> bool {
+        if uri.scheme_str() == Some("internal") || uri.scheme().is_none() {
+            return true;
+        }
+
+        match uri.authority() {
+            Some(authority) => self
+                .origins
+                .iter()
+                .any(|origin| origin == authority.as_str()),
+            None => false,
+        }
+    }
+}
+
+impl<S> HttpClientFactory for InternalClient<S>
+where
+    S: Service<Request<axum::body::Body>, Response = axum::response::Response, Error = Infallible>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    S::Future: Send,
+{
+    type Client<B> = Self
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Into<Bytes> + Send,
+        B::Error: Into<reggie::Error> + Send;
+
+    fn create<B>(&self) -> Self::Client<B>
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Into<Bytes> + Send,
+        B::Error: Into<reggie::Error> + Send,
+    {
+        self.clone()
+    }
+}
+
+impl<S, B> HttpClient<B> for InternalClient<S>
+where
+    S: Service<Request<axum::body::Body>, Response = axum::response::Response, Error = Infallible>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    S::Future: Send,
+    B: HttpBody + Send + 'static,
+    B::Data: Into<Bytes> + Send,
+    B::Error: Into<reggie::Error> + Send,
+{
+    type Body = Body;
+
+    type Future<'a> = BoxFuture<'a, Result<Response<Self::Body>, reggie::Error>>
+    where
+        Self: 'a;
+
+    fn send<'a>(&'a self, request: Request<B>) -> Self::Future<'a> {
+        Box::pin(async move {
+            let request = request.map(|body| Body::from_streaming(body.map_err(Into::into)));
+
+            if !self.is_internal(request.uri()) {
+                let Some(external) = &self.external else {
+                    return Err(reggie::Error::Body(
+                        format!("no external client for: {}", request.uri()).into(),
+                    ));
+                };
+
+                return external.create().send(request).await;
+            }
+
+            let (mut parts, body) = request.into_parts();
+
+            parts.uri = parts
+                .uri
+                .path_and_query()
+                .map(|path| Uri::from(path.clone()))
+                .unwrap_or_else(|| Uri::from_static("/"));
+
+            request_headers(|headers| {
+                for name in self.forward.iter() {
+                    if parts.headers.contains_key(name) {
+                        continue;
+                    }
+                    for value in headers.get_all(name) {
+                        parts.headers.append(name.clone(), value.clone());
+                    }
+                }
+            });
+
+            let request = Request::from_parts(parts, axum::body::Body::new(body));
+
+            let response = self
+                .service
+                .clone()
+                .oneshot(request)
+                .await
+                .unwrap_or_else(|err| match err {});
+
+            Ok(response.map(|body| {
+                Body::from_streaming(body.map_err(|err| reggie::Error::Body(Box::new(err))))
+            }))
+        })
+    }
+}
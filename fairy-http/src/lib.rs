@@ -1,9 +1,17 @@
-pub mod config;
-mod dev;
+mod builder;
+mod csrf;
+mod internal;
+mod metrics;
+mod proxy;
 mod render;
-mod service;
 mod template;
 
 pub use self::{
-    dev::ViteDevService, render::RenderService, service::ViteService, template::Template,
+    builder::{FairyService, FairyServiceBuilder},
+    csrf::Csrf,
+    internal::{with_request_headers, InternalClient},
+    metrics::{MetricsService, PrometheusMetrics},
+    proxy::{ViteProxy, ViteProxyLayer, ViteProxyService},
+    render::FairyRenderService,
+    template::{DefaultTemplate, ErrorHandler, Template},
 };
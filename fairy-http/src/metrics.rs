@@ -0,0 +1,317 @@
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    fmt::Write,
+    future::{ready, Ready},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::Poll,
+    time::Duration,
+};
+
+use axum::{http::Request, response::Response};
+use fairy_render::{PoolStatus, RenderMetrics, RenderTimings};
+use reggie::Body;
+use tower_service::Service;
+
+const BUCKETS: [f64; 11] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        for (bucket, le) in self.buckets.iter().zip(BUCKETS) {
+            if secs <= le {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn write(&self, out: &mut String, name: &str, help: &str) {
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        for (bucket, le) in self.buckets.iter().zip(BUCKETS) {
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{le=\"{le}\"}} {}",
+                bucket.load(Ordering::Relaxed)
+            );
+        }
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}");
+        let _ = writeln!(
+            out,
+            "{name}_sum {}",
+            self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.
+        );
+        let _ = writeln!(out, "{name}_count {count}");
+    }
+}
+
+/// [`RenderMetrics`] collected in memory and exported in the Prometheus text format
+#[derive(Debug, Default)]
+pub struct PrometheusMetrics {
+    vm_wait: Histogram,
+    import: Histogram,
+    render: Histogram,
+    request: Histogram,
+    render_bytes: AtomicU64,
+    errors: Mutex<BTreeMap<&'static str, u64>>,
+    requests: Mutex<BTreeMap<u16, u64>>,
+    pool_max_size: AtomicUsize,
+    pool_size: AtomicUsize,
+    pool_available: AtomicUsize,
+    pool_waiting: AtomicUsize,
+}
+
+impl PrometheusMetrics {
+    pub fn new() -> Arc<PrometheusMetrics> {
+        Arc::default()
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format
+    pub fn encode(&self) -> String {
+        let mut out = String::new();
+
+        self.vm_wait.write(
+            &mut out,
+            "fairy_vm_wait_seconds",
+            "Time spent waiting for a pooled vm",
+        );
+        self.import.write(
+            &mut out,
+            "fairy_module_import_seconds",
+            "Time spent importing the server module",
+        );
+        self.render.write(
+            &mut out,
+            "fairy_render_seconds",
+            "Time spent in the render function",
+        );
+        self.request.write(
+            &mut out,
+            "fairy_request_duration_seconds",
+            "Time spent handling requests",
+        );
+
+        let _ = writeln!(out, "# HELP fairy_render_bytes_total Rendered bytes");
+        let _ = writeln!(out, "# TYPE fairy_render_bytes_total counter");
+        let _ = writeln!(
+            out,
+            "fairy_render_bytes_total {}",
+            self.render_bytes.load(Ordering::Relaxed)
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP fairy_render_errors_total Failed renders by kind"
+        );
+        let _ = writeln!(out, "# TYPE fairy_render_errors_total counter");
+        for (kind, count) in self.errors.lock().unwrap().iter() {
+            let _ = writeln!(out, "fairy_render_errors_total{{kind=\"{kind}\"}} {count}");
+        }
+
+        let _ = writeln!(out, "# HELP fairy_requests_total Responses by status");
+        let _ = writeln!(out, "# TYPE fairy_requests_total counter");
+        for (status, count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(out, "fairy_requests_total{{status=\"{status}\"}} {count}");
+        }
+
+        for (name, help, value) in [
+            (
+                "fairy_pool_max_size",
+                "Maximum number of vms",
+                &self.pool_max_size,
+            ),
+            ("fairy_pool_size", "Number of vms", &self.pool_size),
+            (
+                "fairy_pool_available",
+                "Number of idle vms",
+                &self.pool_available,
+            ),
+            (
+                "fairy_pool_waiting",
+                "Renders waiting for a vm",
+                &self.pool_waiting,
+            ),
+        ] {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} gauge");
+            let _ = writeln!(out, "{name} {}", value.load(Ordering::Relaxed));
+        }
+
+        out
+    }
+
+    /// Service responding with the encoded metrics
+    pub fn service(self: &Arc<Self>) -> MetricsService {
+        MetricsService {
+            metrics: self.clone(),
+        }
+    }
+}
+
+impl RenderMetrics for PrometheusMetrics {
+    fn record_render(&self, timings: &RenderTimings, size: usize) {
+        self.vm_wait.observe(timings.vm_wait);
+        self.import.observe(timings.import);
+        self.render.observe(timings.render);
+        self.render_bytes.fetch_add(size as u64, Ordering::Relaxed);
+    }
+
+    fn record_error(&self, kind: &'static str) {
+        *self.errors.lock().unwrap().entry(kind).or_default() += 1;
+    }
+
+    fn record_pool(&self, status: PoolStatus) {
+        self.pool_max_size.store(status.max_size, Ordering::Relaxed);
+        self.pool_size.store(status.size, Ordering::Relaxed);
+        self.pool_available
+            .store(status.available, Ordering::Relaxed);
+        self.pool_waiting.store(status.waiting, Ordering::Relaxed);
+    }
+
+    fn record_request(&self, duration: Duration, status: u16) {
+        self.request.observe(duration);
+        *self.requests.lock().unwrap().entry(status).or_default() += 1;
+    }
+}
+
+#[derive(Clone)]
+pub struct MetricsService {
+    metrics: Arc<PrometheusMetrics>,
+}
+
+impl<B> Service<Request<B>> for MetricsService {
+    type Response = Response<Body>;
+
+    type Error = Infallible;
+
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &mut self,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _req: Request<B>) -> Self::Future {
+        let resp = Response::builder()
+            .header("Content-Type", "text/plain; version=0.0.4")
+            .status(200)
+            .body(Body::from(self.metrics.encode()))
+            .expect("build response");
+
+        ready(Ok(resp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics() -> Arc<PrometheusMetrics> {
+        let metrics = PrometheusMetrics::new();
+
+        metrics.record_render(
+            &RenderTimings {
+                vm_wait: Duration::from_millis(3),
+                import: Duration::from_millis(20),
+                render: Duration::from_millis(200),
+                fetch: Duration::ZERO,
+            },
+            100,
+        );
+        metrics.record_error("engine");
+        metrics.record_error("engine");
+        metrics.record_request(Duration::from_millis(30), 200);
+        metrics.record_request(Duration::from_millis(30), 404);
+        metrics.record_pool(PoolStatus {
+            max_size: 4,
+            size: 2,
+            available: 1,
+            waiting: 0,
+        });
+
+        metrics
+    }
+
+    #[test]
+    fn encodes_text_format() {
+        let out = metrics().encode();
+        let lines = out.lines().collect::<Vec<_>>();
+
+        for expected in [
+            "# HELP fairy_vm_wait_seconds Time spent waiting for a pooled vm",
+            "# TYPE fairy_vm_wait_seconds histogram",
+            "fairy_vm_wait_seconds_bucket{le=\"0.001\"} 0",
+            "fairy_vm_wait_seconds_bucket{le=\"0.005\"} 1",
+            "fairy_vm_wait_seconds_bucket{le=\"5\"} 1",
+            "fairy_vm_wait_seconds_bucket{le=\"+Inf\"} 1",
+            "fairy_vm_wait_seconds_sum 0.003",
+            "fairy_vm_wait_seconds_count 1",
+            "fairy_render_seconds_bucket{le=\"0.1\"} 0",
+            "fairy_render_seconds_bucket{le=\"0.25\"} 1",
+            "fairy_request_duration_seconds_count 2",
+            "# TYPE fairy_render_bytes_total counter",
+            "fairy_render_bytes_total 100",
+            "fairy_render_errors_total{kind=\"engine\"} 2",
+            "fairy_requests_total{status=\"200\"} 1",
+            "fairy_requests_total{status=\"404\"} 1",
+            "# TYPE fairy_pool_size gauge",
+            "fairy_pool_max_size 4",
+            "fairy_pool_size 2",
+            "fairy_pool_available 1",
+            "fairy_pool_waiting 0",
+        ] {
+            assert!(lines.contains(&expected), "missing {expected:?} in\n{out}");
+        }
+    }
+
+    #[test]
+    fn samples_follow_their_type() {
+        let out = metrics().encode();
+        let mut family = None;
+
+        for line in out.lines() {
+            if let Some(rest) = line.strip_prefix("# TYPE ") {
+                family = rest.split_whitespace().next();
+                continue;
+            }
+            if line.starts_with("# HELP ") {
+                continue;
+            }
+
+            let (name, value) = line.rsplit_once(' ').expect("sample has a value");
+            let name = name.split('{').next().unwrap();
+            let family = family.expect("sample after a TYPE line");
+
+            assert!(name.starts_with(family), "{name} outside of {family}");
+            assert!(value.parse::<f64>().is_ok(), "{line}");
+        }
+    }
+
+    #[tokio::test]
+    async fn service_responds_with_text_format() {
+        let metrics = metrics();
+
+        let resp = metrics.service().call(Request::new(())).await.unwrap();
+
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers()["content-type"], "text/plain; version=0.0.4");
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    convert::Infallible,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    http::{
+        header, uri::Authority, HeaderMap, HeaderName, HeaderValue, Request, Response, StatusCode,
+        Uri,
+    },
+};
+use futures::future::BoxFuture;
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::{TokioExecutor, TokioIo},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Paths served by the vite dev server
+const DEV_PATHS: &[&str] = &["/@vite/", "/@fs/", "/@id/", "/src/", "/node_modules/"];
+
+/// Exact paths served by the vite dev server
+const DEV_FILES: &[&str] = &["/@react-refresh"];
+
+/// Time allowed to connect to the dev server and complete a websocket handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Reverse proxy to the vite dev server, so the browser only talks to this server.
+///
+/// Plain requests are forwarded with an http client. Websocket upgrades (vite's hmr connection)
+/// are upgraded on both sides and the connections joined. Only `http://` origins are supported.
+#[derive(Clone)]
+pub struct ViteProxy {
+    origin: Uri,
+    client: Client<HttpConnector, Body>,
+    timeout: Duration,
+}
+
+impl ViteProxy {
+    pub fn new(origin: &str) -> Result<ViteProxy, axum::http::uri::InvalidUri> {
+        Ok(ViteProxy {
+            origin: origin.trim_end_matches('/').parse()?,
+            client: Client::builder(TokioExecutor::new()).build_http(),
+            timeout: HANDSHAKE_TIMEOUT,
+        })
+    }
+
+    /// Time allowed to connect to the dev server and complete a websocket handshake
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Whether `req` should be handled by the dev server
+    pub fn matches<B>(req: &Request<B>) -> bool {
+        let path = req.uri().path();
+        is_hmr(req.headers())
+            || DEV_FILES.contains(&path)
+            || DEV_PATHS.iter().any(|prefix| path.starts_with(prefix))
+    }
+
+    fn authority(&self) -> Option<&Authority> {
+        self.origin.authority()
+    }
+
+    fn target(&self, uri: &Uri) -> Result<Uri, axum::http::Error> {
+        let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        let scheme = self.origin.scheme_str().unwrap_or("http");
+        let authority = self.authority().map(Authority::as_str).unwrap_or_default();
+        Ok(Uri::try_from(format!("{scheme}://{authority}{path}"))?)
+    }
+
+    async fn forward(self, mut req: Request<Body>) -> Response<Body> {
+        let uri = match self.target(req.uri()) {
+            Ok(uri) => uri,
+            Err(err) => return bad_gateway(err),
+        };
+
+        *req.uri_mut() = uri;
+        remove_hop_headers(req.headers_mut());
+        // Let the client set the host of the dev server
+        req.headers_mut().remove(header::HOST);
+
+        match self.client.request(req).await {
+            Ok(resp) => {
+                let mut resp = resp.map(Body::new);
+                remove_hop_headers(resp.headers_mut());
+                resp
+            }
+            Err(err) => bad_gateway(err),
+        }
+    }
+
+    async fn tunnel(self, mut req: Request<Body>) -> Response<Body> {
+        let uri = match self.target(req.uri()) {
+            Ok(uri) => uri,
+            Err(err) => return bad_gateway(err),
+        };
+
+        let mut upstream = Request::builder()
+            .method(req.method())
+            .uri(uri)
+            .body(Body::empty())
+            .expect("build request");
+
+        *upstream.headers_mut() = upgrade_headers(req.headers());
+        upstream.headers_mut().remove(header::HOST);
+
+        let mut resp = match tokio::time::timeout(self.timeout, self.client.request(upstream)).await
+        {
+            Ok(Ok(resp)) => resp,
+            Ok(Err(err)) => return bad_gateway(err),
+            Err(_) => return gateway_timeout(),
+        };
+
+        if resp.status() != StatusCode::SWITCHING_PROTOCOLS {
+            let mut resp = resp.map(Body::new);
+            remove_hop_headers(resp.headers_mut());
+            return resp;
+        }
+
+        let server = hyper::upgrade::on(&mut resp);
+        let client = hyper::upgrade::on(&mut req);
+
+        tokio::spawn(async move {
+            let (server, client) = match futures::future::try_join(server, client).await {
+                Ok(upgraded) => upgraded,
+                Err(err) => {
+                    tracing::warn!(error = %err, "websocket upgrade failed");
+                    return;
+                }
+            };
+
+            let mut server = TokioIo::new(server);
+            let mut client = TokioIo::new(client);
+
+            if let Err(err) = tokio::io::copy_bidirectional(&mut client, &mut server).await {
+                tracing::debug!(error = %err, "websocket tunnel closed");
+            }
+        });
+
+        let mut switched = Response::new(Body::empty());
+        *switched.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+        *switched.headers_mut() = upgrade_headers(resp.headers());
+        switched
+    }
+}
+
+impl Service<Request<Body>> for ViteProxy {
+    type Response = Response<Body>;
+
+    type Error = Infallible;
+
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let proxy = self.clone();
+        Box::pin(async move {
+            if is_websocket(req.headers()) {
+                Ok(proxy.tunnel(req).await)
+            } else {
+                Ok(proxy.forward(req).await)
+            }
+        })
+    }
+}
+
+/// Sends dev server paths and websocket upgrades to a [`ViteProxy`],
+/// everything else to the wrapped service
+#[derive(Clone)]
+pub struct ViteProxyLayer {
+    proxy: ViteProxy,
+}
+
+impl ViteProxyLayer {
+    pub fn new(proxy: ViteProxy) -> ViteProxyLayer {
+        ViteProxyLayer { proxy }
+    }
+}
+
+impl<S> Layer<S> for ViteProxyLayer {
+    type Service = ViteProxyService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ViteProxyService {
+            proxy: self.proxy.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ViteProxyService<S> {
+    proxy: ViteProxy,
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for ViteProxyService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+
+    type Error = Infallible;
+
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        if ViteProxy::matches(&req) {
+            self.proxy.call(req)
+        } else {
+            Box::pin(self.inner.call(req))
+        }
+    }
+}
+
+/// Vite's client connects with the `vite-hmr` (or `vite-ping`) websocket protocol
+fn is_hmr(headers: &HeaderMap) -> bool {
+    is_websocket(headers)
+        && headers
+            .get(header::SEC_WEBSOCKET_PROTOCOL)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("vite-"))
+}
+
+fn is_websocket(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::UPGRADE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+}
+
+/// Removes headers meant for a single connection, including the ones listed in `connection`
+fn remove_hop_headers(headers: &mut HeaderMap) {
+    let listed = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect::<Vec<_>>();
+
+    for name in listed {
+        headers.remove(name);
+    }
+
+    let proxy = headers
+        .keys()
+        .filter(|name| name.as_str().starts_with("proxy-"))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    for name in proxy {
+        headers.remove(name);
+    }
+
+    for name in [
+        header::CONNECTION,
+        header::PROXY_AUTHENTICATE,
+        header::PROXY_AUTHORIZATION,
+        header::TE,
+        header::TRAILER,
+        header::TRANSFER_ENCODING,
+        header::UPGRADE,
+        HeaderName::from_static("keep-alive"),
+    ] {
+        headers.remove(name);
+    }
+}
+
+/// `headers` without hop-by-hop headers, asking to switch to the websocket protocol
+fn upgrade_headers(headers: &HeaderMap) -> HeaderMap {
+    let mut headers = headers.clone();
+    remove_hop_headers(&mut headers);
+    headers.insert(header::CONNECTION, HeaderValue::from_static("upgrade"));
+    headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+    headers
+}
+
+fn bad_gateway(err: impl std::fmt::Display) -> Response<Body> {
+    tracing::warn!(error = %err, "vite dev server request failed");
+
+    Response::builder()
+        .status(StatusCode::BAD_GATEWAY)
+        .body(Body::from(format!("vite dev server: {err}")))
+        .expect("build response")
+}
+
+fn gateway_timeout() -> Response<Body> {
+    tracing::warn!("vite dev server did not answer the websocket handshake in time");
+
+    Response::builder()
+        .status(StatusCode::GATEWAY_TIMEOUT)
+        .body(Body::from("vite dev server: websocket handshake timed out"))
+        .expect("build response")
+}
@@ -3,42 +3,103 @@ use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::Poll;
+use std::time::{Duration, Instant};
 
-use axum::http::Uri;
-use fairy_render::quick::Quick;
-use fairy_vite::{FairyRenderer, Vite, ViteEntry};
+use axum::extract::{FromRequestParts, RawPathParams};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
+use fairy_render::{ActionPayload, RenderMetrics, PARAMS_HEADER};
+use fairy_vite::{FairyRenderer, FairyResult, Timings};
 use reggie::bytes::Bytes;
 use reggie::http::{Request, Response};
 use reggie::http_body::Body as HttpBody;
-use reggie::http_body_util::BodyExt;
+use reggie::http_body_util::{BodyExt, LengthLimitError, Limited};
 use reggie::Body;
 use tower_service::Service;
+use tracing::Instrument;
 
-use crate::template::Template;
+use crate::{
+    csrf::Csrf,
+    internal::with_request_headers,
+    template::{ErrorHandler, Template},
+};
+
+/// Default limit of action bodies, the same as axum's `DefaultBodyLimit`
+pub(crate) const DEFAULT_BODY_LIMIT: usize = 2 * 1024 * 1024;
 
 #[derive(Clone)]
-pub struct RenderService {
+pub struct FairyRenderService {
     fairy: FairyRenderer,
     template: Arc<dyn Template + Send + Sync>,
+    csrf: Option<Arc<Csrf>>,
+    metrics: Option<Arc<dyn RenderMetrics>>,
+    server_timing: bool,
+    status: StatusCode,
+    error_handler: Option<Arc<dyn ErrorHandler + Send + Sync>>,
+    body_limit: usize,
 }
 
-impl RenderService {
-    pub fn new<T>(fairy: FairyRenderer, func: T) -> RenderService
+impl FairyRenderService {
+    pub fn new<T>(fairy: FairyRenderer, func: T) -> FairyRenderService
     where
         T: Template + Send + Sync + 'static,
     {
-        RenderService {
+        FairyRenderService {
             fairy,
             template: Arc::new(func),
+            csrf: None,
+            metrics: None,
+            server_timing: false,
+            status: StatusCode::OK,
+            error_handler: None,
+            body_limit: DEFAULT_BODY_LIMIT,
         }
     }
+
+    /// Require a valid csrf token on actions
+    pub fn csrf(mut self, csrf: Csrf) -> Self {
+        self.csrf = Some(Arc::new(csrf));
+        self
+    }
+
+    /// Record request durations and statuses
+    pub fn metrics(mut self, metrics: Arc<dyn RenderMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Emit a `Server-Timing` header with the duration of each render phase.
+    /// Usually only enabled in development or staging
+    pub fn server_timing(mut self, enabled: bool) -> Self {
+        self.server_timing = enabled;
+        self
+    }
+
+    /// Status of rendered pages, eg. `404 Not Found` for a fallback rendering the app's
+    /// not found page. Defaults to `200 OK`
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Maximum size of action bodies in bytes, larger bodies are answered with
+    /// `413 Payload Too Large`. Defaults to 2MB
+    pub fn body_limit(mut self, limit: usize) -> Self {
+        self.body_limit = limit;
+        self
+    }
+
+    /// Respond to failed renders with `handler`, instead of the template with a `500` status
+    pub fn on_error(mut self, handler: Arc<dyn ErrorHandler + Send + Sync>) -> Self {
+        self.error_handler = Some(handler);
+        self
+    }
 }
 
-impl<B> Service<Request<B>> for RenderService
+impl<B> Service<Request<B>> for FairyRenderService
 where
     B: HttpBody + Send + 'static,
     B::Error: std::error::Error + Send + Sync + 'static,
-    B::Data: Into<Bytes>,
+    B::Data: Into<Bytes> + Send,
 {
     type Response = Response<Body>;
 
@@ -56,96 +117,279 @@ where
     fn call(&mut self, req: Request<B>) -> Self::Future {
         let quick = self.fairy.clone();
         let template = self.template.clone();
-        Box::pin(async move {
+        let csrf = self.csrf.clone();
+        let metrics = self.metrics.clone();
+        let server_timing = self.server_timing;
+        let status = self.status;
+        let error_handler = self.error_handler.clone();
+        let body_limit = self.body_limit;
+        let start = Instant::now();
+
+        let span = tracing::info_span!(
+            "fairy_request",
+            method = %req.method(),
+            uri = %req.uri(),
+            status = tracing::field::Empty,
+        );
+
+        let future = async move {
             let uri = req.uri().clone();
+            let headers = req.headers().clone();
+
+            // Path parameters are only present when served through a route
+            let (mut parts, body) = req.into_parts();
+            let params = RawPathParams::from_request_parts(&mut parts, &())
+                .await
+                .ok();
+            expose_params(&mut parts.headers, params.as_ref());
+            let mut req = Request::from_parts(parts, body);
+
+            if req.uri().scheme().is_none() {
+                *req.uri_mut() = format!("internal://internal.com{}", uri)
+                    .parse()
+                    .expect("url");
+            }
 
-            let result = quick
-                .render(req.map(|m| {
+            let csrf_token = csrf.as_ref().map(|csrf| match csrf.token(req.headers()) {
+                Some(token) => (token, false),
+                None => (Csrf::generate(), true),
+            });
+
+            let action = is_action(req.method());
+
+            let result = if action {
+                let (mut parts, body) = req.into_parts();
+
+                let body = match Limited::new(body, body_limit).collect().await {
+                    Ok(body) => body.to_bytes(),
+                    Err(err) if err.is::<LengthLimitError>() => {
+                        return Ok(status_response(StatusCode::PAYLOAD_TOO_LARGE));
+                    }
+                    Err(_) => return Ok(status_response(StatusCode::BAD_REQUEST)),
+                };
+
+                let payload = match parse_payload(&parts.headers, &body) {
+                    Ok(payload) => payload,
+                    Err(status) => return Ok(status_response(status)),
+                };
+
+                if let Some(csrf) = &csrf {
+                    if !csrf.validate(&parts.headers, &payload) {
+                        return Ok(status_response(StatusCode::FORBIDDEN));
+                    }
+                }
+
+                if let (Some(csrf), Some((token, _))) = (&csrf, &csrf_token) {
+                    expose_token(&mut parts.headers, csrf, token);
+                }
+
+                with_request_headers(
+                    headers,
+                    quick.action(Request::from_parts(parts, Body::from(body)), payload),
+                )
+                .await
+            } else {
+                if let (Some(csrf), Some((token, _))) = (&csrf, &csrf_token) {
+                    expose_token(req.headers_mut(), csrf, token);
+                }
+
+                let req = req.map(|m| {
                     reggie::Body::from_streaming(
                         m.map_err(|err| reggie::Error::Body(Box::new(err))),
                     )
-                }))
-                .await;
+                });
+
+                with_request_headers(headers, quick.render(req)).await
+            };
+
+            if let Ok(FairyResult {
+                redirect: Some(location),
+                ..
+            }) = &result
+            {
+                // Actions redirect with see other, so the target is fetched with a GET
+                let status = if action {
+                    StatusCode::SEE_OTHER
+                } else {
+                    StatusCode::FOUND
+                };
+                return Ok(redirect_response(status, location));
+            }
+
+            let status = match &result {
+                Ok(_) => status,
+                Err(err) => {
+                    tracing::error!(error = %err, "render failed");
+
+                    if let Some(handler) = &error_handler {
+                        return Ok(handler.handle(&uri, err).map(|body| {
+                            Body::from_streaming(
+                                body.map_err(|err| reggie::Error::Body(Box::new(err))),
+                            )
+                        }));
+                    }
 
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            };
+
+            let timings = result.as_ref().ok().map(|result| result.timings);
+
+            let template_start = Instant::now();
             let output = template.render(uri, result);
+            let template_time = template_start.elapsed();
 
-            let resp = Response::builder()
+            let mut resp = Response::builder()
                 .header("Content-Type", "text/html")
-                .status(200)
+                .status(status)
                 .body(Body::from(output))
                 .expect("build response");
 
+            if let (true, Some(timings)) = (server_timing, timings) {
+                if let Ok(value) =
+                    HeaderValue::from_str(&format_server_timing(&timings, template_time))
+                {
+                    resp.headers_mut().insert("server-timing", value);
+                }
+            }
+
+            if let (Some(csrf), Some((token, true))) = (&csrf, &csrf_token) {
+                match csrf.set_cookie(token) {
+                    Some(cookie) => {
+                        resp.headers_mut().append(header::SET_COOKIE, cookie);
+                    }
+                    None => tracing::error!("invalid csrf cookie, see Csrf::check"),
+                }
+            }
+
             Ok(resp)
-        })
+        };
+
+        Box::pin(
+            async move {
+                let resp = future.await;
+
+                if let Ok(resp) = &resp {
+                    tracing::Span::current().record("status", resp.status().as_u16());
+                }
+
+                if let (Some(metrics), Ok(resp)) = (&metrics, &resp) {
+                    metrics.record_request(start.elapsed(), resp.status().as_u16());
+                }
+
+                resp
+            }
+            .instrument(span),
+        )
     }
 }
 
-#[derive(Clone)]
-pub struct FairyRenderService {
-    fairy: FairyRenderer,
-    template: Arc<dyn Template + Send + Sync>,
+fn format_server_timing(timings: &Timings, template: Duration) -> String {
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.;
+
+    format!(
+        "vm;desc=\"VM acquisition\";dur={:.3}, \
+         import;desc=\"Module import\";dur={:.3}, \
+         render;desc=\"JS render\";dur={:.3}, \
+         fetch;desc=\"JS fetch\";dur={:.3}, \
+         assets;desc=\"Asset resolution\";dur={:.3}, \
+         template;desc=\"Template\";dur={:.3}",
+        ms(timings.render.vm_wait),
+        ms(timings.render.import),
+        ms(timings.render.render),
+        ms(timings.render.fetch),
+        ms(timings.assets),
+        ms(template),
+    )
 }
 
-impl FairyRenderService {
-    pub fn new<T>(fairy: FairyRenderer, func: T) -> FairyRenderService
-    where
-        T: Template + Send + Sync + 'static,
-    {
-        FairyRenderService {
-            fairy,
-            template: Arc::new(func),
-        }
+fn is_action(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Parses the body of an action, failing with the status to respond with.
+/// Only urlencoded forms and json are supported, other bodies must be empty
+fn parse_payload(headers: &HeaderMap, body: &Bytes) -> Result<ActionPayload, StatusCode> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    if content_type.starts_with("application/x-www-form-urlencoded") {
+        Ok(ActionPayload::Form(
+            form_urlencoded::parse(body).into_owned().collect(),
+        ))
+    } else if content_type.starts_with("application/json") {
+        serde_json::from_slice::<serde_json::Value>(body).map_err(|_| StatusCode::BAD_REQUEST)?;
+        String::from_utf8(body.to_vec())
+            .map(ActionPayload::Json)
+            .map_err(|_| StatusCode::BAD_REQUEST)
+    } else if body.is_empty() {
+        Ok(ActionPayload::Empty)
+    } else {
+        Err(StatusCode::UNSUPPORTED_MEDIA_TYPE)
     }
 }
 
-impl<B> Service<Request<B>> for FairyRenderService
-where
-    B: HttpBody + Send + 'static,
-    B::Error: std::error::Error + Send + Sync + 'static,
-    B::Data: Into<Bytes>,
-{
-    type Response = Response<Body>;
+fn expose_token(headers: &mut HeaderMap, csrf: &Csrf, token: &str) {
+    if let (Ok(name), Ok(value)) = (
+        HeaderName::from_bytes(csrf.header_name().as_bytes()),
+        HeaderValue::from_str(token),
+    ) {
+        headers.insert(name, value);
+    }
+}
 
-    type Error = Infallible;
+/// Replaces any client supplied params header with the parameters of the matched route
+fn expose_params(headers: &mut HeaderMap, params: Option<&RawPathParams>) {
+    headers.remove(PARAMS_HEADER);
 
-    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+    let Some(params) = params else {
+        return;
+    };
 
-    fn poll_ready(
-        &mut self,
-        _cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Result<(), Self::Error>> {
-        Poll::Ready(Ok(()))
-    }
+    let params = params
+        .iter()
+        .map(|(name, value)| (name.to_string(), serde_json::Value::from(value)))
+        .collect::<serde_json::Map<_, _>>();
 
-    fn call(&mut self, mut req: Request<B>) -> Self::Future {
-        let quick = self.fairy.clone();
-        let template = self.template.clone();
-        Box::pin(async move {
-            let uri = req.uri().clone();
+    let json = serde_json::Value::Object(params).to_string();
 
-            if req.uri().scheme().is_none() {
-                *req.uri_mut() = format!("internal://internal.com{}", uri)
-                    .parse()
-                    .expect("url");
+    // Header values must be ascii, so escape everything else as json does for control characters
+    let mut escaped = String::with_capacity(json.len());
+    for c in json.chars() {
+        if c.is_ascii() {
+            escaped.push(c);
+        } else {
+            let mut buf = [0u16; 2];
+            for unit in c.encode_utf16(&mut buf) {
+                escaped.push_str(&format!("\\u{unit:04x}"));
             }
+        }
+    }
 
-            let result = quick
-                .render(req.map(|m| {
-                    reggie::Body::from_streaming(
-                        m.map_err(|err| reggie::Error::Body(Box::new(err))),
-                    )
-                }))
-                .await;
+    if let Ok(value) = HeaderValue::from_str(&escaped) {
+        headers.insert(HeaderName::from_static(PARAMS_HEADER), value);
+    }
+}
 
-            let output = template.render(uri, result);
+/// Redirects to a location set by the server bundle, which may not be a valid header value
+fn redirect_response(status: StatusCode, location: &str) -> Response<Body> {
+    let Ok(location) = HeaderValue::from_str(location) else {
+        tracing::error!(location, "render redirected to an invalid location");
+        return status_response(StatusCode::INTERNAL_SERVER_ERROR);
+    };
 
-            let resp = Response::builder()
-                .header("Content-Type", "text/html")
-                .status(200)
-                .body(Body::from(output))
-                .expect("build response");
+    Response::builder()
+        .header(header::LOCATION, location)
+        .status(status)
+        .body(Body::empty())
+        .expect("build response")
+}
 
-            Ok(resp)
-        })
-    }
+fn status_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .expect("build response")
 }
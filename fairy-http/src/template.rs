@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
-use axum::http::Uri;
-use fairy_vite::{FairyResult, ViteError};
+use axum::{http::Uri, response::Response};
+use fairy_vite::{AssetKind, AssetRole, FairyResult, ViteError};
 
 pub trait Template {
     fn render(&self, uri: Uri, request: Result<FairyResult, ViteError>) -> String;
@@ -12,3 +12,87 @@ impl Template for Arc<dyn Template + Send + Sync> {
         (**self).render(uri, request)
     }
 }
+
+/// Turns a failed render into a response, eg. to choose the status or show an error page
+pub trait ErrorHandler {
+    fn handle(&self, uri: &Uri, error: &ViteError) -> Response;
+}
+
+impl<F> ErrorHandler for F
+where
+    F: Fn(&Uri, &ViteError) -> Response,
+{
+    fn handle(&self, uri: &Uri, error: &ViteError) -> Response {
+        (self)(uri, error)
+    }
+}
+
+/// Minimal html document with the rendered head, content and assets.
+/// Content is rendered into `<div id="root">`. Failed renders get a generic error page,
+/// the error itself is only logged
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultTemplate;
+
+impl Template for DefaultTemplate {
+    fn render(&self, _uri: Uri, request: Result<FairyResult, ViteError>) -> String {
+        let Ok(result) = request else {
+            return "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+                    <title>Internal Server Error</title></head>\
+                    <body><h1>Internal Server Error</h1></body></html>"
+                .to_string();
+        };
+
+        let mut head = String::new();
+        let mut body = String::new();
+
+        for asset in &result.assets {
+            let mut attrs = format!("href=\"{}\"", escape(&asset.file));
+            if let Some(integrity) = &asset.integrity {
+                attrs.push_str(&format!(" integrity=\"{}\"", escape(integrity)));
+            }
+            if let Some(crossorigin) = asset.crossorigin {
+                attrs.push_str(&format!(" crossorigin=\"{}\"", crossorigin.as_str()));
+            }
+
+            match (asset.role, asset.kind) {
+                (AssetRole::Stylesheet, _) => {
+                    head.push_str(&format!("<link rel=\"stylesheet\" {attrs}>"));
+                }
+                (AssetRole::Preload, AssetKind::Script) => {
+                    head.push_str(&format!("<link rel=\"modulepreload\" {attrs}>"));
+                }
+                (AssetRole::Preload, kind) => {
+                    if let Some(ty) = kind.as_type() {
+                        head.push_str(&format!("<link rel=\"preload\" as=\"{ty}\" {attrs}>"));
+                    }
+                }
+                (AssetRole::Entry, AssetKind::Script) => {
+                    let attrs = attrs.replacen("href", "src", 1);
+                    body.push_str(&format!("<script type=\"module\" {attrs}></script>"));
+                }
+                (AssetRole::Entry, _) => {}
+            }
+        }
+
+        format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\">{}{head}</head>\
+             <body><div id=\"root\">{}</div>{body}</body></html>",
+            result.head.join(""),
+            String::from_utf8_lossy(&result.content)
+        )
+    }
+}
+
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::{client, get, Build, ENV, HELLO};
+use fairy_http::FairyService;
+use fairy_vite::{ServeMode, ViteError, MODE_ENV};
+
+#[tokio::test]
+async fn prod_renders_from_build_output() {
+    let build = Build::new(HELLO);
+
+    let router = FairyService::builder(build.config())
+        .http(client())
+        .mode(ServeMode::Prod)
+        .build()
+        .await
+        .unwrap();
+
+    let (resp, body) = get(&router, "/").await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(body.contains("<h1>Hello</h1>"), "{body}");
+    assert!(body.contains("/assets/entry-client.js"), "{body}");
+
+    let (resp, body) = get(&router, "/assets/entry-client.js").await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(body, "console.log('client');");
+}
+
+#[tokio::test]
+async fn prod_requires_http_client() {
+    let build = Build::new(HELLO);
+
+    let err = FairyService::builder(build.config())
+        .mode(ServeMode::Prod)
+        .build()
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, ViteError::MissingHttpClient), "{err}");
+}
+
+#[tokio::test]
+async fn dev_serves_client_entry_from_dev_server() {
+    let build = Build::new(HELLO);
+
+    let router = FairyService::builder(build.config())
+        .mode(ServeMode::Dev)
+        .build()
+        .await
+        .unwrap();
+
+    let (resp, body) = get(&router, "/some/page").await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(body.contains("/@vite/client"), "{body}");
+    assert!(body.contains("/src/entry-client.js"), "{body}");
+    assert!(!body.contains("<h1>Hello</h1>"), "{body}");
+}
+
+#[tokio::test]
+async fn mode_env_overrides_config() {
+    let _env = ENV.lock().unwrap_or_else(|err| err.into_inner());
+    let build = Build::new(HELLO);
+
+    let mut config = build.config();
+    config.mode = ServeMode::Prod;
+
+    std::env::set_var(MODE_ENV, "dev");
+    assert_eq!(config.serve_mode(), ServeMode::Dev);
+
+    // Dev mode doesn't render on the server, so no http client is needed
+    let router = FairyService::builder(config.clone()).build().await;
+
+    std::env::set_var(MODE_ENV, "not-a-mode");
+    assert_eq!(config.serve_mode(), ServeMode::Prod);
+
+    std::env::remove_var(MODE_ENV);
+    assert_eq!(config.serve_mode(), ServeMode::Prod);
+
+    let (_, body) = get(&router.unwrap(), "/").await;
+    assert!(body.contains("/@vite/client"), "{body}");
+}
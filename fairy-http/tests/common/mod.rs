@@ -0,0 +1,124 @@
+#![allow(dead_code)]
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use axum::{body::Body, http::Request, response::Response, Router};
+use fairy_http::InternalClient;
+use fairy_vite::ViteConfig;
+use tower::ServiceExt;
+
+/// Held by tests reading or writing process wide environment variables
+pub static ENV: Mutex<()> = Mutex::new(());
+
+/// Server module rendering a static page
+pub const HELLO: &str = r#"export default function render(req) { return "<h1>Hello</h1>"; }"#;
+
+/// A fake vite build output in a temporary directory
+pub struct Build {
+    dir: PathBuf,
+}
+
+impl Build {
+    /// Creates a build with a single entry, rendered by `server`
+    pub fn new(server: &str) -> Build {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = std::env::temp_dir().join(format!(
+            "fairy-http-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+
+        let build = Build { dir };
+
+        build.write(
+            "dist/client/.vite/manifest.json",
+            r#"{
+                "src/entry-client.js": {
+                    "file": "assets/entry-client.js",
+                    "src": "src/entry-client.js",
+                    "isEntry": true
+                }
+            }"#,
+        );
+        build.write("dist/client/.vite/ssr-manifest.json", "{}");
+        build.write(
+            "dist/client/assets/entry-client.js",
+            "console.log('client');",
+        );
+        build.write(
+            "dist/server/.vite/manifest.json",
+            r#"{
+                "src/entry-server.js": {
+                    "file": "entry-server.js",
+                    "src": "src/entry-server.js",
+                    "isEntry": true
+                }
+            }"#,
+        );
+        build.write("dist/server/entry-server.js", server);
+
+        build
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn write(&self, path: &str, content: &str) {
+        let path = self.dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    pub fn config(&self) -> ViteConfig {
+        serde_json::from_value(serde_json::json!({
+            "workDir": self.dir,
+            "root": "dist",
+            "entries": {
+                "client": "src/entry-client.js",
+                "server": "src/entry-server.js"
+            },
+            "base": "/",
+            "port": 5173,
+            "assets": "assets",
+            "assetsPath": "/assets",
+            "clientManifest": "client/.vite/manifest.json",
+            "serverManifest": "server/.vite/manifest.json",
+            "ssrManifest": "client/.vite/ssr-manifest.json"
+        }))
+        .unwrap()
+    }
+}
+
+impl Drop for Build {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+/// Http client for renders, without access to the network
+pub fn client() -> InternalClient<Router> {
+    InternalClient::new(Router::new())
+}
+
+pub async fn send(router: &Router, request: Request<Body>) -> (Response, String) {
+    let response = router.clone().oneshot(request).await.unwrap();
+    let (parts, body) = response.into_parts();
+    let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+
+    (
+        Response::from_parts(parts, Body::empty()),
+        String::from_utf8_lossy(&body).into_owned(),
+    )
+}
+
+pub async fn get(router: &Router, uri: &str) -> (Response, String) {
+    send(router, Request::get(uri).body(Body::empty()).unwrap()).await
+}
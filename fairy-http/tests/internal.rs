@@ -0,0 +1,60 @@
+use axum::{
+    http::{header, HeaderMap},
+    routing::get,
+    Router,
+};
+use fairy_http::{with_request_headers, InternalClient};
+use fairy_render::{
+    reggie::{
+        self, http::Request, http_body_util::BodyExt, Body, HttpClient, HttpClientFactory,
+        SharedClientFactory,
+    },
+    FetchCache, MemoryStore,
+};
+
+/// Internal api answering with the forwarded cookie, cacheable for a minute
+fn api() -> Router {
+    Router::new().route(
+        "/me",
+        get(|headers: HeaderMap| async move {
+            let user = headers
+                .get(header::COOKIE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or("anonymous")
+                .to_string();
+
+            ([(header::CACHE_CONTROL, "max-age=60")], user)
+        }),
+    )
+}
+
+async fn me(client: &SharedClientFactory, cookie: Option<&str>) -> String {
+    let mut headers = HeaderMap::new();
+    if let Some(cookie) = cookie {
+        headers.insert(header::COOKIE, cookie.parse().unwrap());
+    }
+
+    let request = Request::get("internal://internal.com/me")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = with_request_headers(headers, client.create().send(request))
+        .await
+        .unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+
+    String::from_utf8_lossy(&body).into_owned()
+}
+
+#[tokio::test]
+async fn cache_does_not_share_forwarded_credentials() {
+    let client = FetchCache::default()
+        .store(MemoryStore::default())
+        .wrap(reggie::factory_arc(InternalClient::new(api())));
+
+    assert_eq!(me(&client, Some("user=alice")).await, "user=alice");
+    assert_eq!(me(&client, None).await, "anonymous");
+    assert_eq!(me(&client, Some("user=bob")).await, "user=bob");
+    // Anonymous responses are still cached
+    assert_eq!(me(&client, None).await, "anonymous");
+}
@@ -0,0 +1,181 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use common::send;
+use fairy_http::{ViteProxy, ViteProxyLayer};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::oneshot,
+};
+
+/// Serves `router` on a local port, returning its origin
+async fn serve(router: Router) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    format!("http://{addr}")
+}
+
+/// Stand-in for the vite dev server's hmr socket, echoing everything after the handshake.
+/// Also returns the request head it received
+async fn echo_websocket() -> (String, oneshot::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (sender, receiver) = oneshot::channel();
+
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        sender.send(read_head(&mut stream).await).ok();
+
+        stream
+            .write_all(
+                b"HTTP/1.1 101 Switching Protocols\r\n\
+                  upgrade: websocket\r\n\
+                  connection: Upgrade\r\n\
+                  sec-websocket-protocol: vite-hmr\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+        let mut buf = [0u8; 1024];
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            stream.write_all(&buf[..n]).await.unwrap();
+        }
+    });
+
+    (format!("http://{addr}"), receiver)
+}
+
+async fn read_head(stream: &mut TcpStream) -> String {
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        let mut byte = [0u8; 1];
+        stream.read_exact(&mut byte).await.unwrap();
+        head.push(byte[0]);
+    }
+    String::from_utf8(head).unwrap()
+}
+
+fn app(origin: &str) -> Router {
+    proxied(ViteProxy::new(origin).unwrap())
+}
+
+fn proxied(proxy: ViteProxy) -> Router {
+    Router::new()
+        .route("/", get(|| async { "app" }))
+        .layer(ViteProxyLayer::new(proxy))
+}
+
+/// Sends a websocket handshake for vite's hmr socket to `app`, with `extra` headers
+async fn handshake(app: &str, extra: &str) -> (TcpStream, String) {
+    let mut stream = TcpStream::connect(app.trim_start_matches("http://"))
+        .await
+        .unwrap();
+
+    let head = format!(
+        "GET /?token=abc HTTP/1.1\r\n\
+         host: localhost\r\n\
+         upgrade: websocket\r\n\
+         sec-websocket-version: 13\r\n\
+         sec-websocket-key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+         sec-websocket-protocol: vite-hmr\r\n\
+         {extra}\r\n"
+    );
+    stream.write_all(head.as_bytes()).await.unwrap();
+
+    let head = read_head(&mut stream).await;
+    (stream, head)
+}
+
+#[tokio::test]
+async fn forwards_dev_server_paths() {
+    let vite = serve(
+        Router::new()
+            .route("/@vite/client", get(|| async { "vite client" }))
+            .route(
+                "/src/main.ts",
+                get(|req: Request<Body>| async move {
+                    format!("main {}", req.uri().query().unwrap_or_default())
+                }),
+            ),
+    )
+    .await;
+
+    let app = app(&vite);
+
+    let (resp, body) = common::get(&app, "/@vite/client").await;
+    assert!(resp.status().is_success());
+    assert_eq!(body, "vite client");
+
+    let (_, body) = common::get(&app, "/src/main.ts?t=123").await;
+    assert_eq!(body, "main t=123");
+
+    let (_, body) = send(&app, Request::get("/").body(Body::empty()).unwrap()).await;
+    assert_eq!(body, "app");
+}
+
+#[tokio::test]
+async fn reports_unreachable_dev_server() {
+    let app = app("http://127.0.0.1:1");
+
+    let (resp, _) = common::get(&app, "/@vite/client").await;
+    assert_eq!(resp.status(), axum::http::StatusCode::BAD_GATEWAY);
+}
+
+#[tokio::test]
+async fn tunnels_hmr_websocket() {
+    let (vite, forwarded) = echo_websocket().await;
+    let app = serve(app(&vite)).await;
+
+    let (mut stream, head) = handshake(
+        &app,
+        "connection: Upgrade, x-hop\r\n\
+         x-hop: 1\r\n\
+         keep-alive: timeout=5\r\n\
+         proxy-authorization: Basic c2VjcmV0\r\n\
+         te: trailers\r\n",
+    )
+    .await;
+    assert!(head.starts_with("HTTP/1.1 101"), "{head}");
+
+    let forwarded = forwarded.await.unwrap().to_lowercase();
+    assert!(forwarded.starts_with("get /?token=abc "), "{forwarded}");
+    assert!(forwarded.contains("upgrade: websocket"), "{forwarded}");
+    assert!(
+        forwarded.contains("sec-websocket-protocol: vite-hmr"),
+        "{forwarded}"
+    );
+    for hop in ["x-hop", "keep-alive", "proxy-authorization", "te:"] {
+        assert!(!forwarded.contains(hop), "{hop} forwarded: {forwarded}");
+    }
+
+    stream.write_all(b"ping").await.unwrap();
+    let mut buf = [0u8; 4];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+}
+
+#[tokio::test]
+async fn times_out_stalled_handshakes() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let vite = format!("http://{}", listener.local_addr().unwrap());
+
+    // Accepts the connection but never answers
+    tokio::spawn(async move {
+        let (_stream, _) = listener.accept().await.unwrap();
+        std::future::pending::<()>().await;
+    });
+
+    let proxy = ViteProxy::new(&vite)
+        .unwrap()
+        .timeout(std::time::Duration::from_millis(100));
+    let app = serve(proxied(proxy)).await;
+
+    let (_, head) = handshake(&app, "connection: Upgrade\r\n").await;
+    assert!(head.starts_with("HTTP/1.1 504"), "{head}");
+}
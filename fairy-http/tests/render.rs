@@ -0,0 +1,236 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    response::IntoResponse,
+    Router,
+};
+use common::{client, get, send, Build};
+use fairy_http::{Csrf, FairyService, PrometheusMetrics};
+use fairy_vite::ServeMode;
+
+const SERVER: &str = r#"
+export async function action(req, payload) {
+  if (payload.to) {
+    return { redirect: payload.to };
+  }
+  return { saved: payload.name };
+}
+
+export default function render(req, ctx) {
+  const path = new URL(req.url).pathname;
+  if (path === "/old") {
+    return { content: "", head: [], redirect: "/new" };
+  }
+  if (path === "/invalid") {
+    return { content: "", head: [], redirect: "/new\r\nx-injected: 1" };
+  }
+  return `<p>${ctx.actionData ? "saved " + ctx.actionData.saved : "page"}</p>`;
+}
+"#;
+
+async fn router(build: &Build, csrf: bool) -> Router {
+    let mut builder = FairyService::builder(build.config())
+        .http(client())
+        .mode(ServeMode::Prod);
+
+    if csrf {
+        builder = builder.csrf(Csrf::default());
+    }
+
+    builder.build().await.unwrap()
+}
+
+fn form(body: &str, cookie: Option<&str>) -> Request<Body> {
+    let mut request =
+        Request::post("/").header(header::CONTENT_TYPE, "application/x-www-form-urlencoded");
+    if let Some(cookie) = cookie {
+        request = request.header(header::COOKIE, format!("fairy-csrf={cookie}"));
+    }
+    request.body(Body::from(body.to_string())).unwrap()
+}
+
+/// Fetches a page and returns the csrf token set in the cookie
+async fn csrf_token(router: &Router) -> String {
+    let (resp, _) = get(router, "/").await;
+    let cookie = resp.headers()[header::SET_COOKIE].to_str().unwrap();
+    let (pair, _) = cookie.split_once(';').unwrap();
+    pair.strip_prefix("fairy-csrf=").unwrap().to_string()
+}
+
+#[tokio::test]
+async fn csrf_accepts_matching_token() {
+    let build = Build::new(SERVER);
+    let router = router(&build, true).await;
+    let token = csrf_token(&router).await;
+
+    let (resp, body) = send(
+        &router,
+        form(&format!("name=a&_csrf={token}"), Some(&token)),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(body.contains("saved a"), "{body}");
+
+    let request = Request::post("/")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::COOKIE, format!("fairy-csrf={token}"))
+        .header("x-csrf-token", &token)
+        .body(Body::from(r#"{"name":"b"}"#))
+        .unwrap();
+    let (resp, body) = send(&router, request).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(body.contains("saved b"), "{body}");
+}
+
+#[tokio::test]
+async fn csrf_rejects_missing_or_wrong_token() {
+    let build = Build::new(SERVER);
+    let router = router(&build, true).await;
+    let token = csrf_token(&router).await;
+
+    let (resp, _) = send(&router, form("name=a", Some(&token))).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let (resp, _) = send(&router, form("name=a&_csrf=wrong", Some(&token))).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let (resp, _) = send(&router, form(&format!("name=a&_csrf={token}"), None)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn rejects_unsupported_bodies() {
+    let build = Build::new(SERVER);
+    let router = router(&build, false).await;
+
+    let request = Request::post("/")
+        .header(header::CONTENT_TYPE, "multipart/form-data; boundary=x")
+        .body(Body::from("--x\r\n\r\nname\r\n--x--"))
+        .unwrap();
+
+    let (resp, _) = send(&router, request).await;
+    assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn redirects_from_render_and_action() {
+    let build = Build::new(SERVER);
+    let router = router(&build, false).await;
+
+    let (resp, _) = get(&router, "/old").await;
+    assert_eq!(resp.status(), StatusCode::FOUND);
+    assert_eq!(resp.headers()[header::LOCATION], "/new");
+
+    let (resp, _) = send(&router, form("to=%2Fdone", None)).await;
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    assert_eq!(resp.headers()[header::LOCATION], "/done");
+}
+
+#[tokio::test]
+async fn invalid_redirect_is_an_error() {
+    let build = Build::new(SERVER);
+    let router = router(&build, false).await;
+
+    let (resp, _) = get(&router, "/invalid").await;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(resp.headers().get("x-injected").is_none());
+}
+
+#[tokio::test]
+async fn failed_renders_are_server_errors() {
+    let build = Build::new(r#"export default function render() { throw new Error("secret"); }"#);
+
+    let router = FairyService::builder(build.config())
+        .http(client())
+        .mode(ServeMode::Prod)
+        .build()
+        .await
+        .unwrap();
+
+    let (resp, body) = get(&router, "/").await;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(!body.contains("secret"), "{body}");
+
+    let router = FairyService::builder(build.config())
+        .http(client())
+        .mode(ServeMode::Prod)
+        .on_error(|_: &axum::http::Uri, _: &fairy_vite::ViteError| {
+            (StatusCode::SERVICE_UNAVAILABLE, "try again later").into_response()
+        })
+        .build()
+        .await
+        .unwrap();
+
+    let (resp, body) = get(&router, "/").await;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body, "try again later");
+}
+
+#[tokio::test]
+async fn failed_renders_are_counted_by_kind() {
+    let build = Build::new(r#"export default function render() { throw new Error("secret"); }"#);
+    let metrics = PrometheusMetrics::new();
+
+    let router = FairyService::builder(build.config())
+        .http(client())
+        .mode(ServeMode::Prod)
+        .metrics(metrics.clone())
+        .build()
+        .await
+        .unwrap();
+
+    let (resp, _) = get(&router, "/").await;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let out = metrics.encode();
+    assert!(
+        out.lines()
+            .any(|line| line == "fairy_render_errors_total{kind=\"engine\"} 1"),
+        "{out}"
+    );
+}
+
+#[tokio::test]
+async fn rejects_oversized_action_bodies() {
+    let build = Build::new(SERVER);
+    let router = FairyService::builder(build.config())
+        .http(client())
+        .mode(ServeMode::Prod)
+        .body_limit(16)
+        .build()
+        .await
+        .unwrap();
+
+    let (resp, body) = send(&router, form("name=a", None)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(body.contains("saved a"), "{body}");
+
+    let (resp, _) = send(&router, form(&format!("name={}", "a".repeat(64)), None)).await;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn rejects_invalid_csrf_names() {
+    let build = Build::new(SERVER);
+
+    for csrf in [
+        Csrf::default().cookie("fairy csrf"),
+        Csrf::default().cookie("fairy;csrf"),
+        Csrf::default().header("x csrf"),
+    ] {
+        let err = FairyService::builder(build.config())
+            .http(client())
+            .mode(ServeMode::Prod)
+            .csrf(csrf)
+            .build()
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(err, fairy_vite::ViteError::InvalidCsrf(_)),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use std::collections::HashMap;
+
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use common::{client, get, send, Build};
+use fairy_http::FairyService;
+use fairy_vite::{Entry, EntryValue, ServeMode, ViteConfig};
+
+const SERVER: &str = r#"
+export const routes = [
+  { path: "/", component: null },
+  { path: "/users", children: [{ path: "/:id" }] },
+  { path: "*404", component: null },
+];
+
+export async function action() {
+  return { saved: true };
+}
+
+export default function render(req, { params }) {
+  const path = new URL(req.url).pathname;
+  return `<p>${path} ${JSON.stringify(params)}</p>`;
+}
+"#;
+
+async fn router(config: ViteConfig, entry: Option<&str>) -> Router {
+    let builder = FairyService::builder(config)
+        .http(client())
+        .mode(ServeMode::Prod);
+
+    match entry {
+        Some(entry) => builder.bundle_routes_of(entry),
+        None => builder.bundle_routes(),
+    }
+    .build()
+    .await
+    .unwrap()
+}
+
+async fn assert_routes(router: &Router) {
+    let (resp, body) = get(router, "/").await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(body.contains("<p>/ {}</p>"), "{body}");
+
+    let (resp, body) = get(router, "/users/1").await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(body.contains(r#"<p>/users/1 {"id":"1"}</p>"#), "{body}");
+
+    // Rendered by the app, so it can show its own not found page
+    let (resp, body) = get(router, "/missing").await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert!(body.contains("<p>/missing {}</p>"), "{body}");
+
+    let request = Request::post("/missing").body(Body::empty()).unwrap();
+    let (resp, body) = send(router, request).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert!(body.is_empty(), "{body}");
+}
+
+#[tokio::test]
+async fn bundle_routes_fall_back_to_not_found() {
+    let build = Build::new(SERVER);
+
+    assert_routes(&router(build.config(), None).await).await;
+}
+
+/// The build's entry, named `app`
+fn named_config(build: &Build) -> ViteConfig {
+    let mut config = build.config();
+    config.entries = EntryValue::Many(HashMap::from([(
+        "app".to_string(),
+        Entry {
+            client: "src/entry-client.js".to_string(),
+            server: "src/entry-server.js".to_string(),
+        },
+    )]));
+    config
+}
+
+#[tokio::test]
+async fn bundle_routes_of_named_entry() {
+    let build = Build::new(SERVER);
+
+    assert_routes(&router(named_config(&build), Some("app")).await).await;
+}
+
+#[tokio::test]
+async fn bundle_routes_share_paths_with_method_routes() {
+    let build = Build::new(SERVER);
+
+    let router = FairyService::builder(named_config(&build))
+        .http(client())
+        .mode(ServeMode::Prod)
+        .route_on("app", "/users/:id", [Method::POST])
+        .bundle_routes_of("app")
+        .build()
+        .await
+        .unwrap();
+
+    // The page is still rendered by the bundle route
+    let (resp, body) = get(&router, "/users/1").await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(body.contains(r#"<p>/users/1 {"id":"1"}</p>"#), "{body}");
+
+    let request = Request::post("/users/1")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("name=a"))
+        .unwrap();
+    let (resp, body) = send(&router, request).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(body.contains(r#"<p>/users/1 {"id":"1"}</p>"#), "{body}");
+}
@@ -0,0 +1,436 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures::{
+    future::{BoxFuture, Shared},
+    FutureExt,
+};
+use reggie::{
+    bytes::Bytes,
+    http::{header, HeaderMap, Method, Request, Response, StatusCode},
+    http_body::Body as HttpBody,
+    http_body_util::BodyExt,
+    Body, HttpClient, HttpClientFactory, SharedClientFactory,
+};
+
+use crate::request_headers;
+
+type InFlight = Shared<BoxFuture<'static, Result<CachedResponse, Arc<String>>>>;
+
+tokio::task_local! {
+    static IN_FLIGHT: Mutex<HashMap<String, InFlight>>;
+}
+
+/// Runs `fut` with its own set of in-flight requests, so identical GETs made
+/// during a render only hit the network once
+pub async fn with_fetch_dedupe<F: Future>(fut: F) -> F::Output {
+    IN_FLIGHT.scope(Mutex::default(), fut).await
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    pub expires: Instant,
+}
+
+impl CachedResponse {
+    fn to_response(&self) -> Response<Body> {
+        let mut resp = Response::new(Body::from(self.body.clone()));
+        *resp.status_mut() = self.status;
+        *resp.headers_mut() = self.headers.clone();
+        resp
+    }
+
+    fn is_fresh(&self) -> bool {
+        self.expires > Instant::now()
+    }
+}
+
+/// Storage for responses cached across renders
+pub trait CacheStore: Send + Sync {
+    fn get(&self, key: &str) -> Option<CachedResponse>;
+    fn put(&self, key: String, response: CachedResponse);
+}
+
+/// In-memory [`CacheStore`], bounded by number of entries
+#[derive(Debug)]
+pub struct MemoryStore {
+    entries: Mutex<HashMap<String, CachedResponse>>,
+    capacity: usize,
+}
+
+impl MemoryStore {
+    pub fn new(capacity: usize) -> MemoryStore {
+        MemoryStore {
+            entries: Mutex::default(),
+            capacity,
+        }
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        MemoryStore::new(1024)
+    }
+}
+
+impl CacheStore for MemoryStore {
+    fn get(&self, key: &str) -> Option<CachedResponse> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    fn put(&self, key: String, response: CachedResponse) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            entries.retain(|_, entry| entry.is_fresh());
+            if entries.len() >= self.capacity {
+                return;
+            }
+        }
+        entries.insert(key, response);
+    }
+}
+
+/// Deduplication and caching of fetches made from SSR code.
+///
+/// Only GET requests are affected. Responses are cached according to
+/// their `Cache-Control` header, and only when a store is configured.
+/// Requests carrying credentials (`Authorization` or `Cookie`) are never cached
+/// or deduplicated, as their responses may differ per user. Neither are fetches made while
+/// rendering a request with credentials (see [`crate::with_request_headers`]), since
+/// clients below the cache may forward them. Responses setting cookies are never stored.
+#[derive(Clone)]
+pub struct FetchCache {
+    store: Option<Arc<dyn CacheStore>>,
+    dedupe: bool,
+}
+
+impl Default for FetchCache {
+    fn default() -> Self {
+        FetchCache {
+            store: None,
+            dedupe: true,
+        }
+    }
+}
+
+impl FetchCache {
+    pub fn store<S: CacheStore + 'static>(mut self, store: S) -> Self {
+        self.store = Some(Arc::new(store));
+        self
+    }
+
+    pub fn dedupe(mut self, dedupe: bool) -> Self {
+        self.dedupe = dedupe;
+        self
+    }
+
+    pub fn wrap(&self, client: SharedClientFactory) -> SharedClientFactory {
+        reggie::factory_arc(CacheClient {
+            inner: client,
+            cache: self.clone(),
+        })
+    }
+}
+
+#[derive(Clone)]
+struct CacheClient {
+    inner: SharedClientFactory,
+    cache: FetchCache,
+}
+
+impl CacheClient {
+    async fn fetch(
+        inner: SharedClientFactory,
+        store: Option<Arc<dyn CacheStore>>,
+        key: String,
+        request: Request<Body>,
+    ) -> Result<CachedResponse, Arc<String>> {
+        let response = inner
+            .create()
+            .send(request)
+            .await
+            .map_err(|err| Arc::new(err.to_string()))?;
+
+        let (parts, body) = response.into_parts();
+        let body = body
+            .collect()
+            .await
+            .map_err(|err| Arc::new(err.to_string()))?
+            .to_bytes();
+
+        let max_age = max_age(&parts.headers);
+
+        let cached = CachedResponse {
+            status: parts.status,
+            expires: Instant::now() + max_age.unwrap_or_default(),
+            headers: parts.headers,
+            body,
+        };
+
+        if let (Some(store), Some(_)) = (store, max_age) {
+            if cached.status == StatusCode::OK {
+                store.put(key, cached.clone());
+            }
+        }
+
+        Ok(cached)
+    }
+}
+
+impl HttpClientFactory for CacheClient {
+    type Client<B> = Self
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Into<Bytes> + Send,
+        B::Error: Into<reggie::Error> + Send;
+
+    fn create<B>(&self) -> Self::Client<B>
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Into<Bytes> + Send,
+        B::Error: Into<reggie::Error> + Send,
+    {
+        self.clone()
+    }
+}
+
+impl<B> HttpClient<B> for CacheClient
+where
+    B: HttpBody + Send + 'static,
+    B::Data: Into<Bytes> + Send,
+    B::Error: Into<reggie::Error> + Send,
+{
+    type Body = Body;
+
+    type Future<'a> = BoxFuture<'a, Result<Response<Self::Body>, reggie::Error>>
+    where
+        Self: 'a;
+
+    fn send<'a>(&'a self, request: Request<B>) -> Self::Future<'a> {
+        Box::pin(async move {
+            let request = request.map(|body| Body::from_streaming(body.map_err(Into::into)));
+
+            if request.method() != Method::GET || !is_shareable(request.headers()) {
+                return self.inner.create().send(request).await;
+            }
+
+            let key = request.uri().to_string();
+
+            // Credentials forwarded from the incoming request make the response per user.
+            // Fetches within a render share its request, so they are still deduplicated
+            let store = self
+                .cache
+                .store
+                .clone()
+                .filter(|_| !request_headers(has_credentials).unwrap_or_default());
+
+            if let Some(store) = &store {
+                if let Some(cached) = store.get(&key).filter(CachedResponse::is_fresh) {
+                    return Ok(cached.to_response());
+                }
+            }
+
+            let fetch = Self::fetch(self.inner.clone(), store, key.clone(), request)
+                .boxed()
+                .shared();
+
+            let fetch = if self.cache.dedupe {
+                IN_FLIGHT
+                    .try_with(|in_flight| {
+                        in_flight
+                            .lock()
+                            .unwrap()
+                            .entry(key)
+                            .or_insert(fetch.clone())
+                            .clone()
+                    })
+                    .unwrap_or(fetch)
+            } else {
+                fetch
+            };
+
+            match fetch.await {
+                Ok(cached) => Ok(cached.to_response()),
+                Err(err) => Err(reggie::Error::Body(err.as_str().into())),
+            }
+        })
+    }
+}
+
+fn has_credentials(headers: &HeaderMap) -> bool {
+    headers.contains_key(header::AUTHORIZATION) || headers.contains_key(header::COOKIE)
+}
+
+/// Whether the response to a request may be shared with other requests for the same url
+fn is_shareable(headers: &HeaderMap) -> bool {
+    if has_credentials(headers) {
+        return false;
+    }
+
+    !headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|directive| matches!(directive.trim(), "no-cache" | "no-store" | "private"))
+}
+
+/// Returns how long a response may be cached for, if at all.
+/// Responses which vary, eg. by `Cookie`, or set cookies are specific to a user
+fn max_age(headers: &HeaderMap) -> Option<Duration> {
+    if headers.contains_key(header::VARY) || headers.contains_key(header::SET_COOKIE) {
+        return None;
+    }
+
+    let mut max_age = None;
+
+    for directive in headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+    {
+        match directive.trim().split_once('=') {
+            Some(("max-age" | "s-maxage", secs)) => {
+                max_age = secs.trim().parse().ok().map(Duration::from_secs);
+            }
+            None if matches!(directive.trim(), "no-store" | "no-cache" | "private") => return None,
+            _ => {}
+        }
+    }
+
+    max_age.filter(|age| !age.is_zero())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{send_text, FnClient},
+        with_request_headers,
+    };
+
+    fn cacheable(request: Request<Body>) -> Response<Body> {
+        let cookie = request
+            .headers()
+            .get(header::COOKIE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("anonymous")
+            .to_string();
+
+        Response::builder()
+            .header(header::CACHE_CONTROL, "max-age=60")
+            .body(Body::from(cookie))
+            .unwrap()
+    }
+
+    fn get(cookie: Option<&str>) -> Request<Body> {
+        let mut request = Request::get("http://api.example.com/me");
+        if let Some(cookie) = cookie {
+            request = request.header(header::COOKIE, cookie);
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn caches_anonymous_requests() {
+        let upstream = FnClient::new(cacheable);
+        let client = FetchCache::default()
+            .store(MemoryStore::default())
+            .wrap(upstream.shared());
+
+        assert_eq!(send_text(&client, get(None)).await.unwrap(), "anonymous");
+        assert_eq!(send_text(&client, get(None)).await.unwrap(), "anonymous");
+        assert_eq!(upstream.calls(), 1);
+    }
+
+    #[tokio::test]
+    async fn does_not_share_responses_between_users() {
+        let upstream = FnClient::new(cacheable);
+        let client = FetchCache::default()
+            .store(MemoryStore::default())
+            .wrap(upstream.shared());
+
+        let (alice, bob) = with_fetch_dedupe(async {
+            let alice = send_text(&client, get(Some("user=alice")));
+            let bob = send_text(&client, get(Some("user=bob")));
+            futures::future::join(alice, bob).await
+        })
+        .await;
+
+        assert_eq!(alice.unwrap(), "user=alice");
+        assert_eq!(bob.unwrap(), "user=bob");
+        assert_eq!(
+            send_text(&client, get(Some("user=carol"))).await.unwrap(),
+            "user=carol"
+        );
+        assert_eq!(upstream.calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_cache_private_requests() {
+        let upstream = FnClient::new(cacheable);
+        let client = FetchCache::default()
+            .store(MemoryStore::default())
+            .wrap(upstream.shared());
+
+        for _ in 0..2 {
+            let request = Request::get("http://api.example.com/me")
+                .header(header::CACHE_CONTROL, "private")
+                .body(Body::empty())
+                .unwrap();
+            send_text(&client, request).await.unwrap();
+        }
+
+        assert_eq!(upstream.calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_store_for_requests_with_credentials() {
+        let upstream = FnClient::new(cacheable);
+        let client = FetchCache::default()
+            .store(MemoryStore::default())
+            .wrap(upstream.shared());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, "user=alice".parse().unwrap());
+
+        with_request_headers(headers, send_text(&client, get(None)))
+            .await
+            .unwrap();
+        assert_eq!(send_text(&client, get(None)).await.unwrap(), "anonymous");
+        assert_eq!(upstream.calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_store_user_specific_responses() {
+        for (name, value) in [
+            (header::SET_COOKIE, "session=1"),
+            (header::VARY, "Cookie"),
+            (header::VARY, "Authorization"),
+            (header::CACHE_CONTROL, "private, max-age=60"),
+        ] {
+            let upstream = FnClient::new(move |_| {
+                Response::builder()
+                    .header(header::CACHE_CONTROL, "max-age=60")
+                    .header(name.clone(), value)
+                    .body(Body::empty())
+                    .unwrap()
+            });
+            let client = FetchCache::default()
+                .store(MemoryStore::default())
+                .wrap(upstream.shared());
+
+            send_text(&client, get(None)).await.unwrap();
+            send_text(&client, get(None)).await.unwrap();
+            assert_eq!(upstream.calls(), 2, "{name}: {value}");
+        }
+    }
+}
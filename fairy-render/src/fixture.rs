@@ -0,0 +1,402 @@
+use core::fmt;
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use futures::future::BoxFuture;
+use reggie::{
+    bytes::Bytes,
+    http::{HeaderName, HeaderValue, Request, Response, StatusCode},
+    http_body::Body as HttpBody,
+    http_body_util::BodyExt,
+    Body, HttpClient, HttpClientFactory, SharedClientFactory,
+};
+
+/// Recorded fetches, stored as JSON
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct Fixture {
+    pub entries: Vec<FixtureEntry>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FixtureEntry {
+    pub method: String,
+    pub url: String,
+    pub status: u16,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    pub body: FixtureBody,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum FixtureBody {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl From<Bytes> for FixtureBody {
+    fn from(value: Bytes) -> Self {
+        match String::from_utf8(value.to_vec()) {
+            Ok(text) => FixtureBody::Text(text),
+            Err(err) => FixtureBody::Binary(err.into_bytes()),
+        }
+    }
+}
+
+impl From<FixtureBody> for Bytes {
+    fn from(value: FixtureBody) -> Self {
+        match value {
+            FixtureBody::Text(text) => text.into(),
+            FixtureBody::Binary(bytes) => bytes.into(),
+        }
+    }
+}
+
+impl Fixture {
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Fixture> {
+        let content = std::fs::read(path)?;
+        serde_json::from_slice(&content).map_err(std::io::Error::other)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let content = serde_json::to_vec_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(path, content)
+    }
+}
+
+#[derive(Debug)]
+pub struct UnmatchedRequest {
+    method: String,
+    url: String,
+}
+
+impl fmt::Display for UnmatchedRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no recorded response for {} {}", self.method, self.url)
+    }
+}
+
+impl std::error::Error for UnmatchedRequest {}
+
+/// Response headers which don't apply to the stored, already decoded body
+const DROPPED_HEADERS: &[&str] = &[
+    "connection",
+    "content-encoding",
+    "content-length",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Response headers holding credentials, which are never written to fixtures
+const REDACTED_HEADERS: &[&str] = &[
+    "authorization",
+    "cookie",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "set-cookie",
+    "www-authenticate",
+];
+
+const REDACTED: &str = "[redacted]";
+
+/// Http client which passes requests on to `inner` and records every exchange.
+///
+/// Responses are buffered so they can be stored. Hop-by-hop and encoding headers
+/// are dropped, and credentials are redacted.
+#[derive(Clone)]
+pub struct Recorder {
+    inner: SharedClientFactory,
+    entries: Arc<Mutex<Vec<FixtureEntry>>>,
+}
+
+impl Recorder {
+    pub fn new(inner: SharedClientFactory) -> Recorder {
+        Recorder {
+            inner,
+            entries: Arc::default(),
+        }
+    }
+
+    pub fn fixture(&self) -> Fixture {
+        Fixture {
+            entries: self.entries.lock().unwrap().clone(),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        self.fixture().save(path)
+    }
+}
+
+impl HttpClientFactory for Recorder {
+    type Client<B> = Self
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Into<Bytes> + Send,
+        B::Error: Into<reggie::Error> + Send;
+
+    fn create<B>(&self) -> Self::Client<B>
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Into<Bytes> + Send,
+        B::Error: Into<reggie::Error> + Send,
+    {
+        self.clone()
+    }
+}
+
+impl<B> HttpClient<B> for Recorder
+where
+    B: HttpBody + Send + 'static,
+    B::Data: Into<Bytes> + Send,
+    B::Error: Into<reggie::Error> + Send,
+{
+    type Body = Body;
+
+    type Future<'a> = BoxFuture<'a, Result<Response<Self::Body>, reggie::Error>>
+    where
+        Self: 'a;
+
+    fn send<'a>(&'a self, request: Request<B>) -> Self::Future<'a> {
+        Box::pin(async move {
+            let request = request.map(|body| Body::from_streaming(body.map_err(Into::into)));
+
+            let method = request.method().to_string();
+            let url = request.uri().to_string();
+
+            let response = self.inner.create().send(request).await?;
+            let (parts, body) = response.into_parts();
+            let body = body.collect().await?.to_bytes();
+
+            self.entries.lock().unwrap().push(FixtureEntry {
+                method,
+                url,
+                status: parts.status.as_u16(),
+                headers: parts
+                    .headers
+                    .iter()
+                    .filter(|(name, _)| !DROPPED_HEADERS.contains(&name.as_str()))
+                    .filter_map(|(name, value)| {
+                        let value = if REDACTED_HEADERS.contains(&name.as_str()) {
+                            REDACTED
+                        } else {
+                            value.to_str().ok()?
+                        };
+                        Some((name.to_string(), value.to_string()))
+                    })
+                    .collect(),
+                body: body.clone().into(),
+            });
+
+            Ok(Response::from_parts(parts, Body::from(body)))
+        })
+    }
+}
+
+/// Http client which serves responses from a [`Fixture`] without network access.
+///
+/// Identical requests are answered in the order they were recorded,
+/// repeating the last response once exhausted.
+#[derive(Clone)]
+pub struct Replayer {
+    entries: Arc<HashMap<(String, String), Vec<FixtureEntry>>>,
+    cursor: Arc<Mutex<HashMap<(String, String), usize>>>,
+}
+
+impl Replayer {
+    pub fn new(fixture: Fixture) -> Replayer {
+        let mut entries = HashMap::<_, Vec<_>>::new();
+        for entry in fixture.entries {
+            entries
+                .entry((entry.method.clone(), entry.url.clone()))
+                .or_default()
+                .push(entry);
+        }
+
+        Replayer {
+            entries: Arc::new(entries),
+            cursor: Arc::default(),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Replayer> {
+        Ok(Replayer::new(Fixture::load(path)?))
+    }
+
+    fn next(&self, key: (String, String)) -> Result<FixtureEntry, UnmatchedRequest> {
+        let Some(entries) = self.entries.get(&key).filter(|entries| !entries.is_empty()) else {
+            return Err(UnmatchedRequest {
+                method: key.0,
+                url: key.1,
+            });
+        };
+
+        let mut cursor = self.cursor.lock().unwrap();
+        let idx = cursor.entry(key).or_default();
+        let entry = entries[(*idx).min(entries.len() - 1)].clone();
+        *idx += 1;
+
+        Ok(entry)
+    }
+}
+
+impl HttpClientFactory for Replayer {
+    type Client<B> = Self
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Into<Bytes> + Send,
+        B::Error: Into<reggie::Error> + Send;
+
+    fn create<B>(&self) -> Self::Client<B>
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Into<Bytes> + Send,
+        B::Error: Into<reggie::Error> + Send,
+    {
+        self.clone()
+    }
+}
+
+impl<B> HttpClient<B> for Replayer
+where
+    B: HttpBody + Send + 'static,
+    B::Data: Into<Bytes> + Send,
+    B::Error: Into<reggie::Error> + Send,
+{
+    type Body = Body;
+
+    type Future<'a> = BoxFuture<'a, Result<Response<Self::Body>, reggie::Error>>
+    where
+        Self: 'a;
+
+    fn send<'a>(&'a self, request: Request<B>) -> Self::Future<'a> {
+        let key = (request.method().to_string(), request.uri().to_string());
+
+        Box::pin(async move {
+            let entry = self
+                .next(key)
+                .map_err(|err| reggie::Error::Body(Box::new(err)))?;
+
+            let mut response = Response::new(Body::from(Bytes::from(entry.body)));
+            *response.status_mut() =
+                StatusCode::from_u16(entry.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+            for (name, value) in entry.headers {
+                if let (Ok(name), Ok(value)) = (
+                    HeaderName::from_bytes(name.as_bytes()),
+                    HeaderValue::from_str(&value),
+                ) {
+                    response.headers_mut().append(name, value);
+                }
+            }
+
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reggie::http::header;
+
+    use super::*;
+    use crate::{
+        testing::{request, FnClient, Page},
+        Renderer,
+    };
+
+    const PRODUCTS: &str = "https://dummyjson.com/products?limit=1";
+
+    fn products() -> FnClient {
+        FnClient::new(|_| {
+            Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .header(header::CONTENT_ENCODING, "gzip")
+                .header(header::CONTENT_LENGTH, "999")
+                .header(header::TRANSFER_ENCODING, "chunked")
+                .header(header::SET_COOKIE, "session=secret")
+                .body(Body::from(r#"{"products":[{"title":"Phone"}]}"#))
+                .unwrap()
+        })
+    }
+
+    /// A module rendering the first product fetched from the api
+    fn page() -> Page {
+        Page::new(
+            "fixture",
+            &format!(
+                "export default async function render(req) {{ \
+                   const resp = await fetch('{PRODUCTS}'); \
+                   const {{ products }} = await resp.json(); \
+                   return `<p>${{products[0].title}}</p>`; \
+                 }}"
+            ),
+        )
+    }
+
+    #[tokio::test]
+    async fn records_without_encoding_headers_and_credentials() {
+        let recorder = Recorder::new(products().shared());
+        let client = reggie::factory_arc(recorder.clone());
+
+        let request = Request::get(PRODUCTS).body(Body::empty()).unwrap();
+        client.create().send(request).await.unwrap();
+
+        let entry = &recorder.fixture().entries[0];
+        let header = |name: &str| {
+            entry
+                .headers
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+
+        assert_eq!(entry.url, PRODUCTS);
+        assert_eq!(header("content-type"), Some("application/json"));
+        assert_eq!(header("set-cookie"), Some(REDACTED));
+        assert_eq!(header("content-encoding"), None);
+        assert_eq!(header("content-length"), None);
+        assert_eq!(header("transfer-encoding"), None);
+    }
+
+    #[tokio::test]
+    async fn renders_offline_from_recording() {
+        let page = page();
+
+        // Record against the "network"
+        let recorder = Recorder::new(products().shared());
+        let quick = page.quick(reggie::factory_arc(recorder.clone()));
+        let live = quick.render("./page.js".into(), request()).await.unwrap();
+        assert_eq!(&live.content[..], b"<p>Phone</p>");
+
+        let path = page.dir().join("fixture.json");
+        recorder.save(&path).unwrap();
+
+        // Replay without it
+        let replayer = Replayer::load(&path).unwrap();
+        let quick = page.quick(reggie::factory_arc(replayer));
+        let replayed = quick.render("./page.js".into(), request()).await.unwrap();
+        assert_eq!(replayed.content, live.content);
+    }
+
+    #[tokio::test]
+    async fn unmatched_requests_fail() {
+        let replayer = reggie::factory_arc(Replayer::new(Fixture::default()));
+
+        let request = Request::get(PRODUCTS).body(Body::empty()).unwrap();
+        let err = replayer.create().send(request).await.unwrap_err();
+
+        assert!(
+            err.to_string()
+                .contains(&format!("no recorded response for GET {PRODUCTS}")),
+            "{err}"
+        );
+    }
+}
@@ -1,5 +1,13 @@
+mod cache;
+mod fixture;
+mod metrics;
+mod policy;
 pub mod quick;
 mod renderer;
+mod request;
+mod snapshot;
+#[cfg(test)]
+mod testing;
 
-pub use self::renderer::*;
+pub use self::{cache::*, fixture::*, metrics::*, policy::*, renderer::*, request::*, snapshot::*};
 pub use reggie;
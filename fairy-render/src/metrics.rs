@@ -0,0 +1,145 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use futures::future::BoxFuture;
+use reggie::{
+    bytes::Bytes,
+    http::{Request, Response},
+    http_body::Body as HttpBody,
+    http_body_util::BodyExt,
+    Body, HttpClient, HttpClientFactory, SharedClientFactory,
+};
+use tracing::Instrument;
+
+tokio::task_local! {
+    static FETCH_TIME: Arc<AtomicU64>;
+}
+
+/// Runs `fut` while summing up the time spent in fetches made through [`timed`] clients
+pub async fn with_fetch_timer<F: Future>(fut: F) -> (F::Output, Duration) {
+    let total = Arc::new(AtomicU64::default());
+    let ret = FETCH_TIME.scope(total.clone(), fut).await;
+    (ret, Duration::from_micros(total.load(Ordering::Relaxed)))
+}
+
+/// Wraps `client` so time spent waiting for responses is recorded
+pub fn timed(client: SharedClientFactory) -> SharedClientFactory {
+    reggie::factory_arc(TimedClient { inner: client })
+}
+
+/// Time spent in the phases of a single render
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenderTimings {
+    /// Waiting for a vm from the pool
+    pub vm_wait: Duration,
+    /// Importing the server module
+    pub import: Duration,
+    /// Running the render function
+    pub render: Duration,
+    /// Sum of time spent in fetch calls
+    pub fetch: Duration,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStatus {
+    pub max_size: usize,
+    pub size: usize,
+    pub available: usize,
+    pub waiting: usize,
+}
+
+/// Sink for render metrics. All methods default to doing nothing
+pub trait RenderMetrics: Send + Sync {
+    /// Called after every successful render
+    fn record_render(&self, timings: &RenderTimings, size: usize) {
+        let _ = (timings, size);
+    }
+
+    /// Called when a render fails, with a short name of the error kind
+    fn record_error(&self, kind: &'static str) {
+        let _ = kind;
+    }
+
+    /// Called after every render with the state of the vm pool
+    fn record_pool(&self, status: PoolStatus) {
+        let _ = status;
+    }
+
+    /// Called by http integrations once a response has been produced
+    fn record_request(&self, duration: Duration, status: u16) {
+        let _ = (duration, status);
+    }
+}
+
+#[derive(Clone)]
+struct TimedClient {
+    inner: SharedClientFactory,
+}
+
+impl HttpClientFactory for TimedClient {
+    type Client<B> = Self
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Into<Bytes> + Send,
+        B::Error: Into<reggie::Error> + Send;
+
+    fn create<B>(&self) -> Self::Client<B>
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Into<Bytes> + Send,
+        B::Error: Into<reggie::Error> + Send,
+    {
+        self.clone()
+    }
+}
+
+impl<B> HttpClient<B> for TimedClient
+where
+    B: HttpBody + Send + 'static,
+    B::Data: Into<Bytes> + Send,
+    B::Error: Into<reggie::Error> + Send,
+{
+    type Body = Body;
+
+    type Future<'a> = BoxFuture<'a, Result<Response<Self::Body>, reggie::Error>>
+    where
+        Self: 'a;
+
+    fn send<'a>(&'a self, request: Request<B>) -> Self::Future<'a> {
+        let span = tracing::info_span!(
+            "ssr_fetch",
+            method = %request.method(),
+            url = %request.uri(),
+            status = tracing::field::Empty,
+        );
+
+        Box::pin(
+            async move {
+                let request = request.map(|body| Body::from_streaming(body.map_err(Into::into)));
+
+                let start = Instant::now();
+                let ret = self.inner.create().send(request).await;
+
+                let _ = FETCH_TIME.try_with(|total| {
+                    total.fetch_add(start.elapsed().as_micros() as u64, Ordering::Relaxed)
+                });
+
+                match &ret {
+                    Ok(resp) => {
+                        tracing::Span::current().record("status", resp.status().as_u16());
+                    }
+                    Err(err) => tracing::warn!(error = %err, "fetch failed"),
+                }
+
+                ret
+            }
+            .instrument(span),
+        )
+    }
+}
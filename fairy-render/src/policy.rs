@@ -0,0 +1,565 @@
+use core::fmt;
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures::future::BoxFuture;
+use reggie::{
+    bytes::Bytes,
+    http::{Method, Request, Response},
+    http_body::Body as HttpBody,
+    http_body_util::{BodyExt, LengthLimitError, Limited},
+    Body, HttpClient, HttpClientFactory, SharedClientFactory,
+};
+
+tokio::task_local! {
+    static BUDGET: Arc<FetchBudget>;
+}
+
+/// Fetch usage for a single render
+#[derive(Debug, Default)]
+pub struct FetchBudget {
+    fetches: AtomicUsize,
+    bytes: AtomicU64,
+}
+
+impl FetchBudget {
+    pub fn fetches(&self) -> usize {
+        self.fetches.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+}
+
+/// Runs `fut` with a fresh fetch budget, so per render limits can be enforced
+pub async fn with_fetch_budget<F: Future>(fut: F) -> F::Output {
+    BUDGET.scope(Arc::default(), fut).await
+}
+
+/// A host pattern. Either an exact host (`api.example.com`),
+/// a wildcard subdomain (`*.example.com`) or anything (`*`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostPattern {
+    Any,
+    Exact(String),
+    Subdomain(String),
+}
+
+impl HostPattern {
+    pub fn matches(&self, host: &str) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Exact(pattern) => pattern.eq_ignore_ascii_case(host),
+            Self::Subdomain(pattern) => {
+                host.len() > pattern.len() + 1
+                    && host[host.len() - pattern.len()..].eq_ignore_ascii_case(pattern)
+                    && host.as_bytes()[host.len() - pattern.len() - 1] == b'.'
+            }
+        }
+    }
+}
+
+impl<'a> From<&'a str> for HostPattern {
+    fn from(value: &'a str) -> Self {
+        if value == "*" {
+            HostPattern::Any
+        } else if let Some(domain) = value.strip_prefix("*.") {
+            HostPattern::Subdomain(domain.to_string())
+        } else {
+            HostPattern::Exact(value.to_string())
+        }
+    }
+}
+
+impl From<String> for HostPattern {
+    fn from(value: String) -> Self {
+        value.as_str().into()
+    }
+}
+
+#[derive(Debug)]
+pub enum FetchPolicyError {
+    HostNotAllowed(String),
+    Timeout(Duration),
+    TooManyFetches(usize),
+    TooManyBytes(u64),
+}
+
+impl fmt::Display for FetchPolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HostNotAllowed(host) => {
+                write!(
+                    f,
+                    "fetch to host '{host}' is not allowed by the fetch policy"
+                )
+            }
+            Self::Timeout(timeout) => write!(f, "fetch timed out after {}ms", timeout.as_millis()),
+            Self::TooManyFetches(max) => {
+                write!(f, "render exceeded the fetch limit of {max} requests")
+            }
+            Self::TooManyBytes(max) => {
+                write!(f, "render exceeded the fetch budget of {max} bytes")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FetchPolicyError {}
+
+impl From<FetchPolicyError> for reggie::Error {
+    fn from(value: FetchPolicyError) -> Self {
+        reggie::Error::Body(Box::new(value))
+    }
+}
+
+/// Restrictions applied to all fetches made from SSR code.
+///
+/// Per render limits are only enforced inside [`with_fetch_budget`],
+/// which `Quick` sets up for every render.
+#[derive(Debug, Clone, Default)]
+pub struct FetchPolicy {
+    allowed_hosts: Vec<HostPattern>,
+    timeout: Option<Duration>,
+    max_fetches: Option<usize>,
+    max_bytes: Option<u64>,
+    retries: u32,
+}
+
+impl FetchPolicy {
+    /// Allow requests to hosts matching `pattern`.
+    /// When no hosts are added, all hosts are allowed. Requests to the app itself,
+    /// relative or `internal://` urls, are always allowed
+    pub fn allow_host(mut self, pattern: impl Into<HostPattern>) -> Self {
+        self.allowed_hosts.push(pattern.into());
+        self
+    }
+
+    /// Time allowed for a fetch, including reading the response body.
+    /// Responses are buffered when this is set
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn max_fetches(mut self, max: usize) -> Self {
+        self.max_fetches = Some(max);
+        self
+    }
+
+    /// Maximum number of response bytes per render.
+    /// Responses are buffered when this is set, and aborted once the budget is exceeded
+    pub fn max_bytes(mut self, max: u64) -> Self {
+        self.max_bytes = Some(max);
+        self
+    }
+
+    /// Number of retries for idempotent requests failing with a transport error or a 5xx
+    /// response. Every attempt counts against [`FetchPolicy::max_fetches`]
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn wrap(self, client: SharedClientFactory) -> SharedClientFactory {
+        reggie::factory_arc(PolicyClient {
+            inner: client,
+            policy: Arc::new(self),
+        })
+    }
+
+    fn check_host(&self, request: &Request<Body>) -> Result<(), FetchPolicyError> {
+        let uri = request.uri();
+        if self.allowed_hosts.is_empty() || uri.scheme_str() == Some("internal") {
+            return Ok(());
+        }
+
+        let Some(host) = uri.host() else {
+            return Ok(());
+        };
+
+        if self
+            .allowed_hosts
+            .iter()
+            .any(|pattern| pattern.matches(host))
+        {
+            Ok(())
+        } else {
+            Err(FetchPolicyError::HostNotAllowed(host.to_string()))
+        }
+    }
+
+    fn check_fetches(&self) -> Result<(), FetchPolicyError> {
+        let Some(max) = self.max_fetches else {
+            return Ok(());
+        };
+
+        let count = BUDGET
+            .try_with(|budget| budget.fetches.fetch_add(1, Ordering::Relaxed) + 1)
+            .unwrap_or_default();
+
+        if count > max {
+            Err(FetchPolicyError::TooManyFetches(max))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Reads `body`, aborting as soon as it exceeds what is left of the byte budget
+    async fn read_body(&self, body: Body) -> Result<Bytes, reggie::Error> {
+        let Some(max) = self.max_bytes else {
+            return Ok(body.collect().await?.to_bytes());
+        };
+
+        let remaining =
+            max.saturating_sub(BUDGET.try_with(|budget| budget.bytes()).unwrap_or_default());
+        let limit = usize::try_from(remaining).unwrap_or(usize::MAX);
+
+        let body = match Limited::new(body, limit).collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(err) if err.is::<LengthLimitError>() => {
+                // Exhaust the budget, the rest of the body was never read
+                let _ = BUDGET
+                    .try_with(|budget| budget.bytes.fetch_add(remaining + 1, Ordering::Relaxed));
+                return Err(FetchPolicyError::TooManyBytes(max).into());
+            }
+            Err(err) => return Err(reggie::Error::Body(err)),
+        };
+
+        self.check_bytes(body.len() as u64)?;
+
+        Ok(body)
+    }
+
+    fn check_bytes(&self, len: u64) -> Result<(), FetchPolicyError> {
+        let Some(max) = self.max_bytes else {
+            return Ok(());
+        };
+
+        let total = BUDGET
+            .try_with(|budget| budget.bytes.fetch_add(len, Ordering::Relaxed) + len)
+            .unwrap_or(len);
+
+        if total > max {
+            Err(FetchPolicyError::TooManyBytes(max))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Clone)]
+struct PolicyClient {
+    inner: SharedClientFactory,
+    policy: Arc<FetchPolicy>,
+}
+
+impl PolicyClient {
+    async fn send_once(&self, request: Request<Body>) -> Result<Response<Body>, reggie::Error> {
+        let fut = async {
+            let response = self.inner.create().send(request).await?;
+
+            if self.policy.timeout.is_none() && self.policy.max_bytes.is_none() {
+                return Ok(response);
+            }
+
+            // Read here, so the timeout covers the body as well
+            let (parts, body) = response.into_parts();
+            let body = self.policy.read_body(body).await?;
+
+            Ok(Response::from_parts(parts, Body::from(body)))
+        };
+
+        match self.policy.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, fut)
+                .await
+                .map_err(|_| FetchPolicyError::Timeout(timeout))?,
+            None => fut.await,
+        }
+    }
+}
+
+impl HttpClientFactory for PolicyClient {
+    type Client<B> = Self
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Into<Bytes> + Send,
+        B::Error: Into<reggie::Error> + Send;
+
+    fn create<B>(&self) -> Self::Client<B>
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Into<Bytes> + Send,
+        B::Error: Into<reggie::Error> + Send,
+    {
+        self.clone()
+    }
+}
+
+impl<B> HttpClient<B> for PolicyClient
+where
+    B: HttpBody + Send + 'static,
+    B::Data: Into<Bytes> + Send,
+    B::Error: Into<reggie::Error> + Send,
+{
+    type Body = Body;
+
+    type Future<'a> = BoxFuture<'a, Result<Response<Self::Body>, reggie::Error>>
+    where
+        Self: 'a;
+
+    fn send<'a>(&'a self, request: Request<B>) -> Self::Future<'a> {
+        Box::pin(async move {
+            let request = request.map(|body| Body::from_streaming(body.map_err(Into::into)));
+
+            self.policy.check_host(&request)?;
+
+            let idempotent = matches!(
+                *request.method(),
+                Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE
+            );
+
+            if !idempotent || self.policy.retries == 0 {
+                self.policy.check_fetches()?;
+                return self.send_once(request).await;
+            }
+
+            // Retries need to replay the body, so buffer it up front
+            let (parts, body) = request.into_parts();
+            let body = body.collect().await?.to_bytes();
+
+            let mut attempt = 0;
+            loop {
+                self.policy.check_fetches()?;
+
+                let mut request = Request::new(Body::from(body.clone()));
+                *request.method_mut() = parts.method.clone();
+                *request.uri_mut() = parts.uri.clone();
+                *request.version_mut() = parts.version;
+                *request.headers_mut() = parts.headers.clone();
+
+                let retry = match self.send_once(request).await {
+                    Ok(response) if response.status().is_server_error() => Ok(response),
+                    Err(err) if !is_policy_error(&err) => Err(err),
+                    ret => return ret,
+                };
+
+                if attempt >= self.policy.retries {
+                    return retry;
+                }
+
+                attempt += 1;
+            }
+        })
+    }
+}
+
+/// Policy violations are deterministic, so they are never retried
+fn is_policy_error(err: &reggie::Error) -> bool {
+    matches!(err, reggie::Error::Body(err) if err.is::<FetchPolicyError>())
+}
+
+#[cfg(test)]
+mod tests {
+    use reggie::{http::StatusCode, http_body::Frame, http_body_util::StreamBody};
+
+    use super::*;
+    use crate::testing::{send_text, FnClient};
+
+    fn ok() -> FnClient {
+        FnClient::new(|_| Response::new(Body::from("response")))
+    }
+
+    fn get(url: &str) -> Request<Body> {
+        Request::get(url).body(Body::empty()).unwrap()
+    }
+
+    fn policy_error(err: reggie::Error) -> FetchPolicyError {
+        match err {
+            reggie::Error::Body(err) => *err.downcast::<FetchPolicyError>().unwrap(),
+            err => panic!("unexpected error: {err}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn allows_matching_hosts() {
+        let upstream = ok();
+        let client = FetchPolicy::default()
+            .allow_host("api.example.com")
+            .allow_host("*.cdn.com")
+            .wrap(upstream.shared());
+
+        for url in [
+            "https://api.example.com/a",
+            "https://eu.cdn.com/b",
+            "/api/relative",
+            "internal://internal.com/api",
+        ] {
+            assert_eq!(send_text(&client, get(url)).await.unwrap(), "response");
+        }
+
+        assert_eq!(upstream.calls(), 4);
+    }
+
+    #[tokio::test]
+    async fn denies_other_hosts() {
+        let upstream = ok();
+        let client = FetchPolicy::default()
+            .allow_host("*.cdn.com")
+            .wrap(upstream.shared());
+
+        for url in [
+            "https://evil.com/",
+            "https://cdn.com/",
+            "https://evilcdn.com/",
+        ] {
+            let err = send_text(&client, get(url)).await.unwrap_err();
+            assert!(matches!(
+                policy_error(err),
+                FetchPolicyError::HostNotAllowed(_)
+            ));
+        }
+
+        assert_eq!(upstream.calls(), 0);
+    }
+
+    #[tokio::test]
+    async fn enforces_fetch_budget_per_render() {
+        let client = FetchPolicy::default().max_fetches(2).wrap(ok().shared());
+
+        with_fetch_budget(async {
+            send_text(&client, get("https://a.com/")).await.unwrap();
+            send_text(&client, get("https://a.com/")).await.unwrap();
+
+            let err = send_text(&client, get("https://a.com/")).await.unwrap_err();
+            assert!(matches!(
+                policy_error(err),
+                FetchPolicyError::TooManyFetches(2)
+            ));
+        })
+        .await;
+
+        // Every render gets its own budget
+        with_fetch_budget(async {
+            send_text(&client, get("https://a.com/")).await.unwrap();
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn enforces_byte_budget_per_render() {
+        let client = FetchPolicy::default().max_bytes(10).wrap(ok().shared());
+
+        with_fetch_budget(async {
+            send_text(&client, get("https://a.com/")).await.unwrap();
+
+            let err = send_text(&client, get("https://a.com/")).await.unwrap_err();
+            assert!(matches!(
+                policy_error(err),
+                FetchPolicyError::TooManyBytes(10)
+            ));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let upstream = FnClient::new(|_| {
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::BAD_GATEWAY;
+            response
+        });
+        let client = FetchPolicy::default().retries(2).wrap(upstream.shared());
+
+        send_text(&client, get("https://a.com/")).await.unwrap();
+        assert_eq!(upstream.calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn aborts_bodies_over_budget() {
+        // Never ends, so it must not be read in full
+        let upstream = FnClient::new(|_| {
+            let chunks = futures::stream::iter(std::iter::repeat_with(|| {
+                Ok::<_, reggie::Error>(Frame::data(Bytes::from_static(b"0123456789")))
+            }));
+            Response::new(Body::from_streaming(StreamBody::new(chunks)))
+        });
+        let client = FetchPolicy::default()
+            .max_bytes(100)
+            .wrap(upstream.shared());
+
+        with_fetch_budget(async {
+            let err = send_text(&client, get("https://a.com/")).await.unwrap_err();
+            assert!(matches!(
+                policy_error(err),
+                FetchPolicyError::TooManyBytes(100)
+            ));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn times_out_stalled_bodies() {
+        let upstream = FnClient::new(|_| {
+            let chunks = futures::stream::pending::<Result<Frame<Bytes>, reggie::Error>>();
+            Response::new(Body::from_streaming(StreamBody::new(chunks)))
+        });
+        let client = FetchPolicy::default()
+            .timeout(Duration::from_millis(50))
+            .wrap(upstream.shared());
+
+        let err = send_text(&client, get("https://a.com/")).await.unwrap_err();
+        assert!(matches!(policy_error(err), FetchPolicyError::Timeout(_)));
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_policy_errors() {
+        let upstream = ok();
+        let client = FetchPolicy::default()
+            .max_bytes(4)
+            .retries(2)
+            .wrap(upstream.shared());
+
+        with_fetch_budget(async {
+            let err = send_text(&client, get("https://a.com/")).await.unwrap_err();
+            assert!(matches!(
+                policy_error(err),
+                FetchPolicyError::TooManyBytes(4)
+            ));
+        })
+        .await;
+
+        assert_eq!(upstream.calls(), 1);
+    }
+
+    #[tokio::test]
+    async fn charges_retries_to_fetch_budget() {
+        let upstream = FnClient::new(|_| {
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::BAD_GATEWAY;
+            response
+        });
+        let client = FetchPolicy::default()
+            .max_fetches(2)
+            .retries(5)
+            .wrap(upstream.shared());
+
+        with_fetch_budget(async {
+            let err = send_text(&client, get("https://a.com/")).await.unwrap_err();
+            assert!(matches!(
+                policy_error(err),
+                FetchPolicyError::TooManyFetches(2)
+            ));
+        })
+        .await;
+
+        assert_eq!(upstream.calls(), 2);
+    }
+}
@@ -0,0 +1,110 @@
+(global, now, seed) => {
+  const RealDate = global.Date;
+
+  // A plain function, so `Date()` keeps working without `new`
+  function FrozenDate(...args) {
+    if (!new.target) {
+      return new RealDate(now).toString();
+    }
+    return args.length === 0 ? new RealDate(now) : new RealDate(...args);
+  }
+
+  FrozenDate.prototype = RealDate.prototype;
+  FrozenDate.now = () => now;
+  FrozenDate.parse = RealDate.parse;
+  FrozenDate.UTC = RealDate.UTC;
+
+  global.Date = FrozenDate;
+
+  // mulberry32
+  let state = seed >>> 0;
+  const random = () => {
+    state = (state + 0x6d2b79f5) | 0;
+    let t = Math.imul(state ^ (state >>> 15), 1 | state);
+    t = (t + Math.imul(t ^ (t >>> 7), 61 | t)) ^ t;
+    return ((t ^ (t >>> 14)) >>> 0) / 4294967296;
+  };
+
+  Math.random = random;
+
+  if (global.crypto) {
+    global.crypto.randomUUID = () => {
+      const hex = [];
+      for (let i = 0; i < 16; i++) {
+        let byte = Math.floor(random() * 256);
+        if (i === 6) byte = (byte & 0x0f) | 0x40;
+        if (i === 8) byte = (byte & 0x3f) | 0x80;
+        hex.push(byte.toString(16).padStart(2, "0"));
+      }
+      return [
+        hex.slice(0, 4).join(""),
+        hex.slice(4, 6).join(""),
+        hex.slice(6, 8).join(""),
+        hex.slice(8, 10).join(""),
+        hex.slice(10, 16).join(""),
+      ].join("-");
+    };
+  }
+
+  // Timers run one per tick in order of (due time, scheduling) on a virtual clock,
+  // without actually waiting. The next timer is picked when the tick fires, so
+  // cleared timers never run. Intervals are scheduled again after every run
+  const realSetTimeout = global.setTimeout;
+  const timers = new Map();
+  let seq = 0;
+  let clock = 0;
+
+  const tick = () => {
+    let next;
+    for (const timer of timers.values()) {
+      if (
+        !next ||
+        timer.at < next.at ||
+        (timer.at === next.at && timer.order < next.order)
+      ) {
+        next = timer;
+      }
+    }
+    if (!next) return;
+    clock = next.at;
+    if (next.repeat) {
+      next.at = clock + next.delay;
+      next.order = ++seq;
+      realSetTimeout(tick, 0);
+    } else {
+      timers.delete(next.id);
+    }
+    next.callback(...next.args);
+  };
+
+  const schedule = (repeat, callback, delay, args) => {
+    const id = ++seq;
+    delay = Math.max(Number(delay) || 0, repeat ? 1 : 0);
+    timers.set(id, { id, order: id, at: clock + delay, delay, repeat, callback, args });
+    realSetTimeout(tick, 0);
+    return id;
+  };
+
+  const clear = (id) => {
+    timers.delete(id);
+  };
+
+  global.setTimeout = (callback, delay = 0, ...args) =>
+    schedule(false, callback, delay, args);
+  global.setInterval = (callback, delay = 0, ...args) =>
+    schedule(true, callback, delay, args);
+  global.clearTimeout = clear;
+  global.clearInterval = clear;
+
+  Object.defineProperty(global, "__fairyResetDeterministic", {
+    value: () => {
+      state = seed >>> 0;
+      seq = 0;
+      clock = 0;
+      timers.clear();
+    },
+    configurable: false,
+    enumerable: false,
+    writable: false,
+  });
+};
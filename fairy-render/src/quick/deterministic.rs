@@ -0,0 +1,115 @@
+const SCRIPT: &str = include_str!("deterministic.js");
+
+/// Makes renders reproducible, for snapshot tests.
+///
+/// Freezes `Date`, seeds `Math.random` and `crypto.randomUUID` and runs timers
+/// in a stable order. The seed is reset before every render.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Deterministic {
+    /// Milliseconds since the unix epoch returned by `Date.now()`
+    pub now: f64,
+    pub seed: u32,
+}
+
+impl Default for Deterministic {
+    fn default() -> Self {
+        Deterministic {
+            // 2024-01-01T00:00:00Z
+            now: 1_704_067_200_000.0,
+            seed: 42,
+        }
+    }
+}
+
+impl Deterministic {
+    pub fn now(mut self, now: f64) -> Self {
+        self.now = now;
+        self
+    }
+
+    pub fn seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub(crate) fn script(&self) -> String {
+        format!("({SCRIPT})(globalThis, {}, {});", self.now, self.seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{request, FnClient, Page},
+        Renderer, RendererFactory,
+    };
+
+    async fn render(name: &str, source: &str) -> String {
+        let page = Page::new(&format!("deterministic-{name}"), source);
+
+        let factory = page.factory().deterministic(Deterministic::default());
+        let client = FnClient::new(|_| unreachable!("no fetches")).shared();
+        let quick = factory.create(client).await.unwrap();
+
+        let ret = quick.render("./page.js".into(), request()).await.unwrap();
+        String::from_utf8(ret.content.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn freezes_date() {
+        let content = render(
+            "date",
+            "export default function render() { \
+               return [typeof Date(), new Date().toISOString(), Date.now(), \
+                       new Date(0).getTime(), new Date() instanceof Date].join(' '); \
+             }",
+        )
+        .await;
+
+        assert_eq!(
+            content,
+            "string 2024-01-01T00:00:00.000Z 1704067200000 0 true"
+        );
+    }
+
+    #[tokio::test]
+    async fn cleared_timers_do_not_run() {
+        let content = render(
+            "timers",
+            "export default async function render() { \
+               const out = []; \
+               const slow = setTimeout(() => out.push('slow'), 10); \
+               const fast = setTimeout(() => { out.push('fast'); clearTimeout(slow); }, 5); \
+               const never = setTimeout(() => out.push('never'), 1); \
+               clearTimeout(never); \
+               await new Promise((resolve) => setTimeout(resolve, 20)); \
+               return out.join(','); \
+             }",
+        )
+        .await;
+
+        assert_eq!(content, "fast");
+    }
+
+    #[tokio::test]
+    async fn intervals_repeat_until_cleared() {
+        let content = render(
+            "intervals",
+            "export default async function render() { \
+               const out = []; \
+               let runs = 0; \
+               const interval = setInterval(() => { \
+                 out.push(`interval${++runs}`); \
+                 if (runs === 3) clearInterval(interval); \
+               }, 10); \
+               setTimeout(() => out.push('timeout'), 25); \
+               await new Promise((resolve) => setTimeout(resolve, 50)); \
+               return out.join(','); \
+             }",
+        )
+        .await;
+
+        assert_eq!(content, "interval1,interval2,timeout,interval3");
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+
+use reggie::{http::Request, http_body_util::BodyExt, Body, SharedClientFactory};
+use rquickjs::{
+    loader::{Loader, Resolver},
+    module::Declared,
+    Ctx, Error, Module, Result,
+};
+use tokio::runtime::Runtime;
+
+/// Resolves and loads modules over http from a running vite dev server.
+///
+/// Absolute paths (`/src/entry-server.tsx`) are fetched from `origin`, relative imports
+/// are resolved against the importing module and bare specifiers are loaded through
+/// vite's `/@id/` prefix. Modules are requested with the `ssr` query. The client is used
+/// for all requests, so tests can substitute the dev server with a local stand-in.
+///
+/// Responses must be plain ES modules. Vite's own ssr transform, as used by
+/// `ssrLoadModule`, rewrites imports and exports to `__vite_ssr_import__` and
+/// `__vite_ssr_exports__`, which can't be evaluated as a module. Such responses are
+/// rejected with a loading error, so the dev server must be set up to serve untransformed
+/// ESM for ssr requests.
+#[derive(Clone)]
+pub struct DevServerLoader {
+    origin: String,
+    client: SharedClientFactory,
+    runtime: Arc<LoaderRuntime>,
+    timeout: Duration,
+}
+
+impl DevServerLoader {
+    pub fn new(origin: impl Into<String>, client: SharedClientFactory) -> DevServerLoader {
+        DevServerLoader {
+            origin: origin.into().trim_end_matches('/').to_string(),
+            client,
+            runtime: Arc::new(LoaderRuntime::new()),
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// How long to wait for a module before failing the import. Defaults to 10 seconds
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    fn url(&self, base: &str, name: &str) -> Option<String> {
+        if name.starts_with("http://") || name.starts_with("https://") {
+            return Some(name.to_string());
+        }
+
+        if name.starts_with('/') {
+            return Some(format!("{}{name}", self.origin));
+        }
+
+        if name.starts_with("./") || name.starts_with("../") {
+            let base = base.strip_prefix(&self.origin)?;
+            let dir = base.split('?').next()?.rsplit_once('/')?.0;
+            let path = relative_path::RelativePath::new(dir)
+                .join_normalized(name)
+                .into_string();
+            return Some(format!("{}/{path}", self.origin));
+        }
+
+        Some(format!("{}/@id/{name}", self.origin))
+    }
+
+    /// Fetches `url` on the loader runtime, blocking until the module has been received.
+    /// Loaders are synchronous and may be called from any runtime flavor, so the fetch
+    /// can't be driven by the calling runtime
+    fn fetch_blocking(&self, url: &str) -> std::result::Result<String, String> {
+        let (sender, receiver) = mpsc::channel();
+        let client = self.client.clone();
+        let url = ssr_url(url);
+
+        let task = self.runtime.get().spawn(async move {
+            sender.send(fetch(&client, &url).await).ok();
+        });
+
+        match receiver.recv_timeout(self.timeout) {
+            Ok(ret) => ret,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                task.abort();
+                Err(format!(
+                    "dev server did not respond within {}ms",
+                    self.timeout.as_millis()
+                ))
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                Err("module fetch was cancelled".to_string())
+            }
+        }
+    }
+}
+
+async fn fetch(client: &SharedClientFactory, url: &str) -> std::result::Result<String, String> {
+    let req = Request::get(url)
+        .body(Body::empty())
+        .map_err(|err| err.to_string())?;
+
+    let resp = client
+        .create()
+        .send(req)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    if !resp.status().is_success() {
+        return Err(format!("dev server responded with {}", resp.status()));
+    }
+
+    let body = resp
+        .into_body()
+        .collect()
+        .await
+        .map_err(|err| err.to_string())?
+        .to_bytes();
+
+    let source = String::from_utf8(body.to_vec()).map_err(|err| err.to_string())?;
+
+    if source.contains("__vite_ssr_import__") || source.contains("__vite_ssr_exports__") {
+        return Err("dev server returned vite's ssr transform instead of an ES module".to_string());
+    }
+
+    Ok(source)
+}
+
+/// Asks the dev server for the ssr transform of a module
+fn ssr_url(url: &str) -> String {
+    match url.split_once('?') {
+        Some((path, query)) => format!("{path}?ssr&{query}"),
+        None => format!("{url}?ssr"),
+    }
+}
+
+/// Runtime driving module fetches, shut down in the background so it can be dropped
+/// from async code
+struct LoaderRuntime(Option<Runtime>);
+
+impl LoaderRuntime {
+    fn new() -> LoaderRuntime {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("fairy-dev-loader")
+            .enable_all()
+            .build()
+            .expect("dev loader runtime");
+
+        LoaderRuntime(Some(runtime))
+    }
+
+    fn get(&self) -> &Runtime {
+        self.0.as_ref().expect("runtime")
+    }
+}
+
+impl Drop for LoaderRuntime {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl Resolver for DevServerLoader {
+    fn resolve<'js>(&mut self, _ctx: &Ctx<'js>, base: &str, name: &str) -> Result<String> {
+        self.url(base, name)
+            .ok_or_else(|| Error::new_resolving(base, name))
+    }
+}
+
+impl Loader for DevServerLoader {
+    fn load<'js>(&mut self, ctx: &Ctx<'js>, name: &str) -> Result<Module<'js, Declared>> {
+        if !name.starts_with(&self.origin) {
+            return Err(Error::new_loading(name));
+        }
+
+        let source = self
+            .fetch_blocking(name)
+            .map_err(|err| Error::new_loading_message(name, err))?;
+
+        Module::declare(ctx.clone(), name, source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reggie::http::{Response, StatusCode};
+    use rquickjs::{Context, Runtime};
+
+    use super::*;
+    use crate::testing::FnClient;
+
+    const ORIGIN: &str = "http://localhost:5173";
+
+    fn dev_server() -> FnClient {
+        FnClient::new(|req| {
+            let source = match (req.uri().path(), req.uri().query()) {
+                ("/src/entry.js", Some("ssr")) => {
+                    "import { double } from './lib/double.js'; \
+                     import { base } from 'some-package'; \
+                     export const value = double(base);"
+                }
+                ("/src/lib/double.js", Some("ssr")) => "export const double = (n) => n * 2;",
+                ("/@id/some-package", Some("ssr")) => "export const base = 21;",
+                ("/src/transformed.js", Some("ssr")) => {
+                    "const __vite_ssr_import_0__ = await __vite_ssr_import__(\"/src/lib/double.js\", \
+                     {\"importedNames\":[\"double\"]});\n\
+                     Object.defineProperty(__vite_ssr_exports__, \"value\", \
+                     { enumerable: true, configurable: true, get(){ return value }});\n\
+                     const value = (0,__vite_ssr_import_0__.double)(21);"
+                }
+                ("/src/hang.js", Some("ssr")) => {
+                    std::thread::sleep(Duration::from_secs(5));
+                    "export const value = 1;"
+                }
+                _ => {
+                    return Response::builder()
+                        .status(StatusCode::NOT_FOUND)
+                        .body(Body::empty())
+                        .unwrap()
+                }
+            };
+
+            Response::new(Body::from(source))
+        })
+    }
+
+    #[test]
+    fn resolves_specifiers() {
+        let loader = DevServerLoader::new(ORIGIN, dev_server().shared());
+        let base = format!("{ORIGIN}/src/pages/index.js");
+
+        assert_eq!(
+            loader.url(&base, "/src/app.js").unwrap(),
+            format!("{ORIGIN}/src/app.js")
+        );
+        assert_eq!(
+            loader.url(&base, "../lib/util.js").unwrap(),
+            format!("{ORIGIN}/src/lib/util.js")
+        );
+        assert_eq!(
+            loader.url(&base, "solid-js/web").unwrap(),
+            format!("{ORIGIN}/@id/solid-js/web")
+        );
+        assert_eq!(
+            ssr_url(&format!("{ORIGIN}/dep.js?v=1")),
+            format!("{ORIGIN}/dep.js?ssr&v=1")
+        );
+    }
+
+    // Loaders are called synchronously from the vm, which must not panic on a current thread runtime
+    #[tokio::test]
+    async fn loads_module_graph_from_dev_server() {
+        let server = dev_server();
+        let loader = DevServerLoader::new(ORIGIN, server.shared());
+
+        let runtime = Runtime::new().unwrap();
+        runtime.set_loader(loader.clone(), loader);
+        let context = Context::full(&runtime).unwrap();
+
+        context.with(|ctx| {
+            Module::evaluate(
+                ctx.clone(),
+                "test",
+                "import { value } from '/src/entry.js'; globalThis.value = value;",
+            )
+            .unwrap()
+            .finish::<()>()
+            .unwrap();
+
+            assert_eq!(ctx.globals().get::<_, i32>("value").unwrap(), 42);
+        });
+
+        assert_eq!(server.calls(), 3);
+    }
+
+    fn import(loader: DevServerLoader, path: &str) -> String {
+        let runtime = Runtime::new().unwrap();
+        runtime.set_loader(loader.clone(), loader);
+        let context = Context::full(&runtime).unwrap();
+
+        context.with(|ctx| {
+            let source = format!("import {{ value }} from '{path}';");
+            let err = Module::evaluate(ctx.clone(), "test", source)
+                .and_then(|promise| promise.finish::<()>())
+                .unwrap_err();
+
+            match err {
+                Error::Exception => ctx.catch().as_exception().unwrap().to_string(),
+                err => err.to_string(),
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn rejects_vite_ssr_transform() {
+        let loader = DevServerLoader::new(ORIGIN, dev_server().shared());
+
+        let err = import(loader, "/src/transformed.js");
+        assert!(err.contains("ssr transform"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn times_out_hanging_dev_server() {
+        let loader =
+            DevServerLoader::new(ORIGIN, dev_server().shared()).timeout(Duration::from_millis(100));
+
+        let err = import(loader, "/src/hang.js");
+        assert!(err.contains("did not respond"), "{err}");
+    }
+}
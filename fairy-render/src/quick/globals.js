@@ -1,34 +1,155 @@
 ((global) => {
   const files = [];
+  // Captured before any deterministic overrides are installed
+  const now = global.Date.now.bind(global.Date);
+
+  const toResult = (ret, importTime) => {
+    if (typeof ret === "string") {
+      return {
+        content: ret,
+        head: [],
+        files: files.slice(),
+        importTime,
+      };
+    }
+    return {
+      ...ret,
+      files: files.slice(),
+      importTime,
+    };
+  };
+
+  const load = async (path) => {
+    const start = now();
+    const module = await import(path);
+    return [module, now() - start];
+  };
+
+  // Set by the server from the matched route, see `PARAMS_HEADER`
+  const params = (req) => {
+    const header = req?.headers?.get("x-fairy-params");
+    if (!header) return {};
+    try {
+      return JSON.parse(header);
+    } catch {
+      return {};
+    }
+  };
+
+  // Accepts paths or route definitions like `{ path, children }`, as used by solid's router
+  const flattenRoutes = (routes, parent) =>
+    routes.flatMap((route) => {
+      if (typeof route === "string") {
+        return [joinPath(parent, route)];
+      }
+
+      const paths = Array.isArray(route.path) ? route.path : [route.path ?? ""];
+
+      return paths.flatMap((path) => {
+        const full = joinPath(parent, path);
+        return Array.isArray(route.children)
+          ? flattenRoutes(route.children, full)
+          : [full];
+      });
+    });
+
+  const joinPath = (parent, path) => {
+    const joined = `${parent.replace(/\/+$/, "")}/${String(path).replace(/^\/+/, "")}`;
+    return joined.length > 1 ? joined.replace(/\/+$/, "") : joined;
+  };
+
+  const reset = () => {
+    files.length = 0;
+    if (typeof global.__fairyResetDeterministic === "function") {
+      global.__fairyResetDeterministic();
+    }
+  };
+
   const Fairy = {
-    runMain: async (path, ...args) => {
-      files.length = 0;
+    runMain: async (path, req) => {
+      reset();
 
-      const { default: render } = await import(path);
+      const [{ default: render }, importTime] = await load(path);
 
       if (typeof render !== "function") {
         throw new TypeError("module does not export function");
       }
 
-      const ret = await Promise.resolve(render(...args));
+      return toResult(
+        await Promise.resolve(render(req, { params: params(req) })),
+        importTime,
+      );
+    },
+    runAction: async (path, req, payload) => {
+      reset();
+
+      const [{ default: render, action }, importTime] = await load(path);
+
+      if (typeof action !== "function") {
+        throw new TypeError("module does not export action");
+      }
+
+      const context = { params: params(req) };
+      const data = await Promise.resolve(action(req, payload, context));
 
-      if (typeof ret === "string") {
+      if (data && typeof data.redirect === "string") {
         return {
-          content: ret,
+          content: "",
           head: [],
-          files: files.slice(),
+          files: [],
+          redirect: data.redirect,
+          importTime,
         };
       }
-      return {
-        ...ret,
-        files: files.slice(),
-      };
+
+      if (typeof render !== "function") {
+        throw new TypeError("module does not export function");
+      }
+
+      return toResult(
+        await Promise.resolve(render(req, { ...context, actionData: data })),
+        importTime,
+      );
+    },
+    checkModule: async (path) => {
+      const { default: render } = await import(path);
+
+      if (typeof render !== "function") {
+        throw new TypeError("module does not export function");
+      }
+    },
+    readRoutes: async (path) => {
+      const { routes } = await import(path);
+
+      if (routes === undefined) {
+        return null;
+      }
+
+      if (!Array.isArray(routes)) {
+        throw new TypeError("routes export is not an array");
+      }
+
+      return flattenRoutes(routes, "");
     },
     pushFile(path) {
       files.push(path);
     },
   };
 
+  // Failed fetches reject with a TypeError, like in browsers. This includes
+  // requests denied by the fetch policy
+  if (typeof global.fetch === "function") {
+    const nativeFetch = global.fetch;
+    global.fetch = async (...args) => {
+      try {
+        return await nativeFetch(...args);
+      } catch (err) {
+        if (err instanceof TypeError) throw err;
+        throw new TypeError(err?.message ?? String(err), { cause: err });
+      }
+    };
+  }
+
   Object.defineProperty(global, "Fairy", {
     value: Object.freeze(Fairy),
     configurable: false,
@@ -1,3 +1,9 @@
+mod deterministic;
+mod dev_loader;
 mod renderer;
 
-pub use self::renderer::{render, Quick, QuickFactory};
+pub use self::{
+    deterministic::Deterministic,
+    dev_loader::DevServerLoader,
+    renderer::{action, render, Isolation, Quick, QuickFactory},
+};
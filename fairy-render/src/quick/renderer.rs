@@ -1,29 +1,42 @@
 use core::fmt;
-use std::path::PathBuf;
+use std::{
+    ops::{Deref, DerefMut},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use futures::{future::BoxFuture, Future};
 use klaver::{
     pool::{Pool, VmPoolOptions},
     Options,
 };
+use tracing::Instrument;
 
 use klaver_wintercg::WinterCG;
-use rquickjs::{self as quick, CatchResultExt, Class, Ctx, FromJs, Object};
+use rquickjs::{self as quick, CatchResultExt, Class, Ctx, FromJs, IntoJs, Object};
 
 use reggie::SharedClientFactory;
 use relative_path::{RelativePath, RelativePathBuf};
 
 use crate::{
-    renderer::{RenderResult, Renderer},
-    RendererFactory,
+    cache::{with_fetch_dedupe, FetchCache},
+    metrics::{timed, with_fetch_timer},
+    policy::{with_fetch_budget, FetchPolicy},
+    renderer::{ActionPayload, RenderResult, Renderer},
+    PoolStatus, RenderMetrics, RenderTimings, RendererFactory,
 };
 
+use super::{deterministic::Deterministic, dev_loader::DevServerLoader};
+
 const GLOBALS: &[u8] = include_bytes!("globals.js");
 
 struct JsResult {
     content: String,
     files: Vec<String>,
     head: Vec<String>,
+    redirect: Option<String>,
+    import_time: Option<f64>,
 }
 
 impl<'js> FromJs<'js> for JsResult {
@@ -36,31 +49,148 @@ impl<'js> FromJs<'js> for JsResult {
             content: obj.get("content")?,
             files: obj.get("files")?,
             head: obj.get("head")?,
+            redirect: obj.get("redirect")?,
+            import_time: obj.get("importTime")?,
         })
     }
 }
 
+impl From<JsResult> for RenderResult {
+    fn from(value: JsResult) -> Self {
+        RenderResult {
+            content: value.content.into(),
+            assets: value.files,
+            head: value.head,
+            redirect: value.redirect,
+            timings: RenderTimings {
+                import: Duration::from_secs_f64(value.import_time.unwrap_or_default() / 1000.),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+struct JsPayload(ActionPayload);
+
+impl<'js> IntoJs<'js> for JsPayload {
+    fn into_js(self, ctx: &Ctx<'js>) -> quick::Result<quick::Value<'js>> {
+        match self.0 {
+            ActionPayload::Empty => Ok(quick::Value::new_undefined(ctx.clone())),
+            ActionPayload::Json(json) => ctx.json_parse(json),
+            ActionPayload::Form(fields) => {
+                let obj = Object::new(ctx.clone())?;
+                for (key, value) in fields {
+                    // Repeated fields (eg. checkboxes) are collected into an array
+                    match obj.get::<_, Option<quick::Value>>(&*key)? {
+                        Some(prev) if prev.is_array() => {
+                            let list = prev.into_array().expect("array");
+                            list.set(list.len(), value)?;
+                        }
+                        Some(prev) => {
+                            let list = quick::Array::new(ctx.clone())?;
+                            list.set(0, prev)?;
+                            list.set(1, value)?;
+                            obj.set(key, list)?;
+                        }
+                        None => obj.set(key, value)?,
+                    }
+                }
+                Ok(obj.into_value())
+            }
+        }
+    }
+}
+
+/// How VMs are shared between renders
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Isolation {
+    /// VMs are returned to the pool and reused. Module level state survives between renders
+    #[default]
+    Shared,
+    /// VMs are discarded after a single render, so every render starts from a fresh context
+    PerRender,
+}
+
+type PooledVm = deadpool::managed::Object<klaver::pool::Manager>;
+
+/// A vm checked out of the pool. With [`Isolation::PerRender`] the vm is removed from
+/// the pool when dropped, also when the render failed or its future was cancelled
+struct Worker {
+    vm: Option<PooledVm>,
+    detach: bool,
+}
+
+impl Deref for Worker {
+    type Target = PooledVm;
+
+    fn deref(&self) -> &Self::Target {
+        self.vm.as_ref().expect("worker")
+    }
+}
+
+impl DerefMut for Worker {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.vm.as_mut().expect("worker")
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        if let (true, Some(vm)) = (self.detach, self.vm.take()) {
+            // Remove the vm from the pool, a fresh one will be created on demand
+            drop(deadpool::managed::Object::take(vm));
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Quick {
     worker: Pool,
+    isolation: Isolation,
+    metrics: Option<Arc<dyn RenderMetrics>>,
 }
 
 impl Quick {
     pub fn new(client: SharedClientFactory, search_paths: Vec<PathBuf>) -> Quick {
+        let mut factory = QuickFactory::default();
+        for sp in search_paths {
+            factory.add_search_path(sp);
+        }
+        Quick::from_factory(client, &factory)
+    }
+
+    pub fn from_factory(client: SharedClientFactory, factory: &QuickFactory) -> Quick {
+        let client = match &factory.cache {
+            Some(cache) => cache.wrap(client),
+            None => client,
+        };
+        let client = match &factory.policy {
+            Some(policy) => policy.clone().wrap(client),
+            None => client,
+        };
+        let client = timed(client);
+
         let mut opts = Options::default();
 
-        for sp in search_paths {
-            opts = opts.search_path(sp);
+        for sp in &factory.search_paths {
+            opts = opts.search_path(sp.clone());
+        }
+
+        if let Some(loader) = &factory.dev_server {
+            opts = opts.resolver(loader.clone()).loader(loader.clone());
         }
 
         let pool_options = VmPoolOptions::from(opts).unwrap();
 
+        let deterministic = factory.deterministic.as_ref().map(Deterministic::script);
+
         let pool = Pool::builder(
             klaver::pool::Manager::new(pool_options)
                 .unwrap()
                 // .use_worker_thread()
                 .init(move |vm| {
                     let client = client.clone();
+                    let deterministic = deterministic.clone();
                     Box::pin(async move {
                         klaver::async_with!(vm => |ctx| {
                             let winter = WinterCG::get(&ctx).catch(&ctx)?;
@@ -73,6 +203,9 @@ impl Quick {
 
                         vm.with(|ctx| {
                             ctx.eval::<(), _>(GLOBALS).catch(&ctx)?;
+                            if let Some(script) = &deterministic {
+                                ctx.eval::<(), _>(script.as_bytes()).catch(&ctx)?;
+                            }
                             Ok(())
                         })
                         .await?;
@@ -83,7 +216,126 @@ impl Quick {
         .build()
         .unwrap();
 
-        Quick { worker: pool }
+        Quick {
+            worker: pool,
+            isolation: factory.isolation,
+            metrics: factory.metrics.clone(),
+        }
+    }
+
+    /// Returns a handle to the same pool using another isolation mode
+    pub fn with_isolation(&self, isolation: Isolation) -> Quick {
+        Quick {
+            worker: self.worker.clone(),
+            isolation,
+            metrics: self.metrics.clone(),
+        }
+    }
+
+    pub fn isolation(&self) -> Isolation {
+        self.isolation
+    }
+
+    pub fn pool_status(&self) -> PoolStatus {
+        let status = self.worker.status();
+        PoolStatus {
+            max_size: status.max_size,
+            size: status.size,
+            available: status.available,
+            waiting: status.waiting,
+        }
+    }
+
+    /// Imports the module at `path` and checks that it exports a render function
+    pub async fn check(&self, path: RelativePathBuf) -> Result<(), QuickRenderError> {
+        let worker = self.checkout().await?;
+
+        klaver::async_with!(worker => |ctx| {
+
+            let fairy: Object = ctx.globals().get("Fairy").catch(&ctx)?;
+            let check_module: quick::Function = fairy.get("checkModule").catch(&ctx)?;
+            check_module
+                .call::<_, quick::Promise>((path.as_str(),))
+                .catch(&ctx)?
+                .into_future::<()>()
+                .await
+                .catch(&ctx)?;
+
+            Ok(())
+
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Imports the module at `path` and returns the paths listed by its `routes` export,
+    /// or `None` when the module has no such export
+    pub async fn routes(
+        &self,
+        path: RelativePathBuf,
+    ) -> Result<Option<Vec<String>>, QuickRenderError> {
+        let worker = self.checkout().await?;
+
+        let routes = klaver::async_with!(worker => |ctx| {
+
+            let fairy: Object = ctx.globals().get("Fairy").catch(&ctx)?;
+            let read_routes: quick::Function = fairy.get("readRoutes").catch(&ctx)?;
+            let routes = read_routes
+                .call::<_, quick::Promise>((path.as_str(),))
+                .catch(&ctx)?
+                .into_future::<Option<Vec<String>>>()
+                .await
+                .catch(&ctx)?;
+
+            Ok(routes)
+
+        })
+        .await?;
+
+        Ok(routes)
+    }
+
+    /// Runs a render with fetch budgets, deduplication, metrics and tracing in place
+    fn run<'a, F>(
+        &'a self,
+        span: tracing::Span,
+        fut: F,
+    ) -> BoxFuture<'a, Result<RenderResult, QuickRenderError>>
+    where
+        F: Future<Output = Result<RenderResult, QuickRenderError>> + Send + 'a,
+    {
+        Box::pin(with_fetch_budget(with_fetch_dedupe(self.observe(fut))).instrument(span))
+    }
+
+    async fn checkout(&self) -> Result<Worker, QuickRenderError> {
+        let vm = self.worker.get().await.map_err(QuickRenderError::Pool)?;
+
+        Ok(Worker {
+            vm: Some(vm),
+            detach: self.isolation == Isolation::PerRender,
+        })
+    }
+
+    async fn observe<F>(&self, fut: F) -> Result<RenderResult, QuickRenderError>
+    where
+        F: Future<Output = Result<RenderResult, QuickRenderError>>,
+    {
+        let (mut ret, fetch) = with_fetch_timer(fut).await;
+
+        if let Ok(ret) = &mut ret {
+            ret.timings.fetch = fetch;
+        }
+
+        if let Some(metrics) = &self.metrics {
+            match &ret {
+                Ok(ret) => metrics.record_render(&ret.timings, ret.content.len()),
+                Err(err) => metrics.record_error(err.kind()),
+            }
+            metrics.record_pool(self.pool_status());
+        }
+
+        ret
     }
 }
 
@@ -178,6 +430,17 @@ pub enum QuickRenderError {
     Script(ScriptError),
 }
 
+impl QuickRenderError {
+    /// Short name of the variant, for metrics and logs
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Engine(_) => "engine",
+            Self::Pool(_) => "pool",
+            Self::Script(_) => "script",
+        }
+    }
+}
+
 impl From<klaver::RuntimeError> for QuickRenderError {
     fn from(value: klaver::RuntimeError) -> Self {
         QuickRenderError::Engine(value)
@@ -207,6 +470,12 @@ impl std::error::Error for QuickRenderError {
 #[derive(Default, Clone)]
 pub struct QuickFactory {
     search_paths: Vec<PathBuf>,
+    cache: Option<FetchCache>,
+    policy: Option<FetchPolicy>,
+    deterministic: Option<Deterministic>,
+    isolation: Isolation,
+    metrics: Option<Arc<dyn RenderMetrics>>,
+    dev_server: Option<DevServerLoader>,
 }
 
 impl QuickFactory {
@@ -219,6 +488,40 @@ impl QuickFactory {
         self.search_paths.push(path.into());
         self
     }
+
+    /// Deduplicate and cache fetches made by the server bundle
+    pub fn fetch_cache(mut self, cache: FetchCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Restrict fetches made by the server bundle. Violations reject `fetch` with a `TypeError`
+    pub fn fetch_policy(mut self, policy: FetchPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Make renders reproducible, see [`Deterministic`]
+    pub fn deterministic(mut self, deterministic: Deterministic) -> Self {
+        self.deterministic = Some(deterministic);
+        self
+    }
+
+    pub fn isolation(mut self, isolation: Isolation) -> Self {
+        self.isolation = isolation;
+        self
+    }
+
+    pub fn metrics<M: RenderMetrics + 'static>(mut self, metrics: Arc<M>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Load modules from a vite dev server instead of the search paths
+    pub fn dev_server(mut self, loader: DevServerLoader) -> Self {
+        self.dev_server = Some(loader);
+        self
+    }
 }
 
 impl RendererFactory for QuickFactory {
@@ -230,7 +533,7 @@ impl RendererFactory for QuickFactory {
         &self,
         fetcher: reggie::SharedClientFactory,
     ) -> impl Future<Output = Result<Self::Renderer, Self::Error>> {
-        async move { Ok(Quick::new(fetcher, self.search_paths.clone())) }
+        async move { Ok(Quick::from_factory(fetcher, self)) }
     }
 }
 
@@ -244,10 +547,14 @@ impl Renderer for Quick {
         path: RelativePathBuf,
         req: reggie::http::Request<reggie::Body>,
     ) -> BoxFuture<'a, Result<crate::renderer::RenderResult, Self::Error>> {
-        Box::pin(async move {
-            let worker = self.worker.get().await.map_err(QuickRenderError::Pool)?;
+        let span = tracing::info_span!("quick_render", path = %path, kind = "render");
 
-            let ret = klaver::async_with!(worker => |ctx| {
+        self.run(span, async move {
+            let start = Instant::now();
+            let worker = self.checkout().await?;
+            let vm_wait = start.elapsed();
+
+            let mut ret = klaver::async_with!(worker => |ctx| {
 
                 let req = klaver_wintercg::http::Request::from_request(&ctx, req).catch(&ctx)?;
                 Ok(render(&ctx, &path, req).await.catch(&ctx)?)
@@ -255,6 +562,37 @@ impl Renderer for Quick {
             })
             .await?;
 
+            ret.timings.vm_wait = vm_wait;
+            ret.timings.render = (start.elapsed() - vm_wait).saturating_sub(ret.timings.import);
+
+            Ok(ret)
+        })
+    }
+
+    fn action<'a>(
+        &'a self,
+        path: RelativePathBuf,
+        req: reggie::http::Request<reggie::Body>,
+        payload: ActionPayload,
+    ) -> BoxFuture<'a, Result<crate::renderer::RenderResult, Self::Error>> {
+        let span = tracing::info_span!("quick_render", path = %path, kind = "action");
+
+        self.run(span, async move {
+            let start = Instant::now();
+            let worker = self.checkout().await?;
+            let vm_wait = start.elapsed();
+
+            let mut ret = klaver::async_with!(worker => |ctx| {
+
+                let req = klaver_wintercg::http::Request::from_request(&ctx, req).catch(&ctx)?;
+                Ok(action(&ctx, &path, req, payload).await.catch(&ctx)?)
+
+            })
+            .await?;
+
+            ret.timings.vm_wait = vm_wait;
+            ret.timings.render = (start.elapsed() - vm_wait).saturating_sub(ret.timings.import);
+
             Ok(ret)
         })
     }
@@ -276,9 +614,25 @@ pub async fn render<'js>(
     let ret = run_main.call::<_, quick::Promise>((path.as_str(), req))?;
     let ret = ret.into_future::<JsResult>().await?;
 
-    Ok(RenderResult {
-        content: ret.content.into(),
-        assets: ret.files,
-        head: ret.head,
-    })
+    Ok(ret.into())
+}
+
+pub async fn action<'js>(
+    ctx: &Ctx<'js>,
+    path: &RelativePath,
+    req: Class<'js, klaver_wintercg::http::Request<'js>>,
+    payload: ActionPayload,
+) -> quick::Result<RenderResult> {
+    let globals = ctx.globals();
+    if !globals.contains_key("Fairy")? {
+        ctx.eval::<(), _>(GLOBALS)?;
+    }
+
+    let fairy: Object = globals.get("Fairy")?;
+    let run_action: quick::Function = fairy.get("runAction")?;
+
+    let ret = run_action.call::<_, quick::Promise>((path.as_str(), req, JsPayload(payload)))?;
+    let ret = ret.into_future::<JsResult>().await?;
+
+    Ok(ret.into())
 }
@@ -4,11 +4,29 @@ use futures::Future;
 use reggie::{bytes::Bytes, http::Request, Body, SharedClientFactory};
 use relative_path::RelativePathBuf;
 
-#[derive(Debug)]
+use crate::RenderTimings;
+
+#[derive(Debug, Default)]
 pub struct RenderResult {
     pub content: Bytes,
     pub assets: Vec<String>,
     pub head: Vec<String>,
+    /// Set when an action asks the client to navigate elsewhere
+    pub redirect: Option<String>,
+    pub timings: RenderTimings,
+}
+
+/// Request header carrying the path parameters of the matched route as a json object.
+/// The js glue exposes them to the server bundle as `params` in the render context
+pub const PARAMS_HEADER: &str = "x-fairy-params";
+
+/// Parsed body of a non-GET request, handed to the `action` export
+#[derive(Debug, Clone, Default)]
+pub enum ActionPayload {
+    #[default]
+    Empty,
+    Form(Vec<(String, String)>),
+    Json(String),
 }
 
 pub trait Renderer {
@@ -17,6 +35,13 @@ pub trait Renderer {
     where
         Self: 'a;
     fn render<'a>(&'a self, path: RelativePathBuf, req: Request<Body>) -> Self::Future<'a>;
+
+    fn action<'a>(
+        &'a self,
+        path: RelativePathBuf,
+        req: Request<Body>,
+        payload: ActionPayload,
+    ) -> Self::Future<'a>;
 }
 
 pub trait RendererFactory {
@@ -33,11 +58,16 @@ impl Renderer for () {
     type Error = Infallible;
     type Future<'a> = core::future::Ready<Result<RenderResult, Self::Error>>;
     fn render<'a>(&'a self, _path: RelativePathBuf, _req: Request<Body>) -> Self::Future<'a> {
-        core::future::ready(Ok(RenderResult {
-            content: Bytes::new(),
-            assets: Default::default(),
-            head: Default::default(),
-        }))
+        core::future::ready(Ok(RenderResult::default()))
+    }
+
+    fn action<'a>(
+        &'a self,
+        _path: RelativePathBuf,
+        _req: Request<Body>,
+        _payload: ActionPayload,
+    ) -> Self::Future<'a> {
+        core::future::ready(Ok(RenderResult::default()))
     }
 }
 
@@ -51,6 +81,15 @@ where
     fn render<'a>(&'a self, path: RelativePathBuf, req: Request<Body>) -> Self::Future<'a> {
         (**self).render(path, req)
     }
+
+    fn action<'a>(
+        &'a self,
+        path: RelativePathBuf,
+        req: Request<Body>,
+        payload: ActionPayload,
+    ) -> Self::Future<'a> {
+        (**self).action(path, req, payload)
+    }
 }
 
 impl<T> Renderer for Option<T>
@@ -66,11 +105,23 @@ where
     fn render<'a>(&'a self, path: RelativePathBuf, req: Request<Body>) -> Self::Future<'a> {
         match self {
             Some(ret) => futures::future::Either::Left(ret.render(path, req)),
-            None => futures::future::Either::Right(core::future::ready(Ok(RenderResult {
-                content: Bytes::new(),
-                assets: Default::default(),
-                head: Default::default(),
-            }))),
+            None => {
+                futures::future::Either::Right(core::future::ready(Ok(RenderResult::default())))
+            }
+        }
+    }
+
+    fn action<'a>(
+        &'a self,
+        path: RelativePathBuf,
+        req: Request<Body>,
+        payload: ActionPayload,
+    ) -> Self::Future<'a> {
+        match self {
+            Some(ret) => futures::future::Either::Left(ret.action(path, req, payload)),
+            None => {
+                futures::future::Either::Right(core::future::ready(Ok(RenderResult::default())))
+            }
         }
     }
 }
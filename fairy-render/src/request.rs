@@ -0,0 +1,22 @@
+use std::future::Future;
+
+use reggie::http::HeaderMap;
+
+tokio::task_local! {
+    static REQUEST_HEADERS: HeaderMap;
+}
+
+/// Makes the headers of the incoming request available to the http clients of a render
+/// for the duration of `fut`, see [`request_headers`].
+///
+/// The render must be driven on the current task, which is the case for the
+/// default `Quick` pool.
+pub async fn with_request_headers<F: Future>(headers: HeaderMap, fut: F) -> F::Output {
+    REQUEST_HEADERS.scope(headers, fut).await
+}
+
+/// Calls `f` with the headers of the incoming request, when running inside
+/// [`with_request_headers`]
+pub fn request_headers<R>(f: impl FnOnce(&HeaderMap) -> R) -> Option<R> {
+    REQUEST_HEADERS.try_with(f).ok()
+}
@@ -0,0 +1,122 @@
+use std::path::Path;
+
+use reggie::{http::Request, Body};
+use relative_path::RelativePathBuf;
+
+use crate::Renderer;
+
+/// Environment variable which, when set, rewrites snapshots instead of comparing them
+pub const UPDATE_SNAPSHOTS: &str = "FAIRY_UPDATE_SNAPSHOTS";
+
+/// Renders `url` with the module at `path` and compares the content with the html stored at `snapshot`.
+///
+/// Snapshots are only written when `FAIRY_UPDATE_SNAPSHOTS` is set, which is
+/// also how new snapshots are created and changes accepted.
+///
+/// # Panics
+/// When the render fails, the snapshot is missing or the output differs from it.
+pub async fn assert_snapshot<R>(
+    renderer: &R,
+    path: impl Into<RelativePathBuf>,
+    url: &str,
+    snapshot: impl AsRef<Path>,
+) where
+    R: Renderer,
+    R::Error: std::fmt::Display,
+{
+    let snapshot = snapshot.as_ref();
+
+    let req = Request::builder()
+        .uri(url)
+        .body(Body::empty())
+        .expect("request");
+
+    let result = match renderer.render(path.into(), req).await {
+        Ok(ret) => ret,
+        Err(err) => panic!("render of {url} failed: {err}"),
+    };
+
+    let actual = String::from_utf8_lossy(&result.content);
+
+    if std::env::var_os(UPDATE_SNAPSHOTS).is_some() {
+        if let Some(parent) = snapshot.parent() {
+            std::fs::create_dir_all(parent).expect("create snapshot directory");
+        }
+        std::fs::write(snapshot, actual.as_bytes()).expect("write snapshot");
+        return;
+    }
+
+    if !snapshot.exists() {
+        panic!(
+            "snapshot {} for {url} does not exist, set {UPDATE_SNAPSHOTS}=1 to create it",
+            snapshot.display()
+        );
+    }
+
+    let expected = std::fs::read_to_string(snapshot).expect("read snapshot");
+
+    if expected == actual {
+        return;
+    }
+
+    let line = expected
+        .lines()
+        .zip(actual.lines())
+        .position(|(expected, actual)| expected != actual)
+        .unwrap_or_else(|| expected.lines().count().min(actual.lines().count()));
+
+    panic!(
+        "snapshot {} does not match render of {url}, first difference at line {}\n\
+         expected: {}\n  actual: {}\n\
+         set {UPDATE_SNAPSHOTS}=1 to update",
+        snapshot.display(),
+        line + 1,
+        expected.lines().nth(line).unwrap_or_default(),
+        actual.lines().nth(line).unwrap_or_default(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        quick::Quick,
+        testing::{FnClient, Page},
+    };
+
+    fn setup(name: &str) -> (Quick, Page) {
+        let page = Page::new(
+            &format!("snapshot-{name}"),
+            "export default function render(req) { return \"<h1>Hello</h1>\"; }",
+        );
+
+        let client = FnClient::new(|_| unreachable!("no fetches")).shared();
+        (page.quick(client), page)
+    }
+
+    #[tokio::test]
+    async fn passes_on_match() {
+        let (quick, page) = setup("match");
+        let snapshot = page.dir().join("page.html");
+        std::fs::write(&snapshot, "<h1>Hello</h1>").unwrap();
+
+        assert_snapshot(&quick, "./page.js", "internal://internal.com/", &snapshot).await;
+    }
+
+    #[tokio::test]
+    async fn fails_when_missing() {
+        let (quick, page) = setup("missing");
+        let snapshot = page.dir().join("page.html");
+
+        let ret = tokio::spawn({
+            let snapshot = snapshot.clone();
+            async move {
+                assert_snapshot(&quick, "./page.js", "internal://internal.com/", snapshot).await
+            }
+        })
+        .await;
+
+        assert!(ret.unwrap_err().is_panic());
+        assert!(!snapshot.exists());
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use futures::future::BoxFuture;
+use reggie::{
+    bytes::Bytes,
+    http::{Request, Response},
+    http_body::Body as HttpBody,
+    http_body_util::BodyExt,
+    Body, HttpClient, HttpClientFactory, SharedClientFactory,
+};
+
+use crate::quick::{Quick, QuickFactory};
+
+type Handler = dyn Fn(Request<Body>) -> Response<Body> + Send + Sync;
+
+/// Http client answering every request with `handler`, counting the calls made
+#[derive(Clone)]
+pub(crate) struct FnClient {
+    handler: Arc<Handler>,
+    calls: Arc<AtomicUsize>,
+}
+
+impl FnClient {
+    pub fn new<F>(handler: F) -> FnClient
+    where
+        F: Fn(Request<Body>) -> Response<Body> + Send + Sync + 'static,
+    {
+        FnClient {
+            handler: Arc::new(handler),
+            calls: Arc::default(),
+        }
+    }
+
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    pub fn shared(&self) -> SharedClientFactory {
+        reggie::factory_arc(self.clone())
+    }
+}
+
+impl HttpClientFactory for FnClient {
+    type Client<B> = Self
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Into<Bytes> + Send,
+        B::Error: Into<reggie::Error> + Send;
+
+    fn create<B>(&self) -> Self::Client<B>
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Into<Bytes> + Send,
+        B::Error: Into<reggie::Error> + Send,
+    {
+        self.clone()
+    }
+}
+
+impl<B> HttpClient<B> for FnClient
+where
+    B: HttpBody + Send + 'static,
+    B::Data: Into<Bytes> + Send,
+    B::Error: Into<reggie::Error> + Send,
+{
+    type Body = Body;
+
+    type Future<'a> = BoxFuture<'a, Result<Response<Self::Body>, reggie::Error>>
+    where
+        Self: 'a;
+
+    fn send<'a>(&'a self, request: Request<B>) -> Self::Future<'a> {
+        Box::pin(async move {
+            let request = request.map(|body| Body::from_streaming(body.map_err(Into::into)));
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok((self.handler)(request))
+        })
+    }
+}
+
+/// Sends `request` with `client` and returns the response body as text
+pub(crate) async fn send_text(
+    client: &SharedClientFactory,
+    request: Request<Body>,
+) -> Result<String, reggie::Error> {
+    let body = client
+        .create()
+        .send(request)
+        .await?
+        .into_body()
+        .collect()
+        .await?
+        .to_bytes();
+
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Temporary directory holding a `page.js` module, removed on drop
+pub(crate) struct Page {
+    dir: PathBuf,
+}
+
+impl Page {
+    pub fn new(name: &str, source: &str) -> Page {
+        let dir = std::env::temp_dir().join(format!("fairy-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("page.js"), source).unwrap();
+        Page { dir }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// A renderer resolving modules from the page directory
+    pub fn quick(&self, client: SharedClientFactory) -> Quick {
+        Quick::new(client, vec![self.dir.clone()])
+    }
+
+    pub fn factory(&self) -> QuickFactory {
+        QuickFactory::default().search_path(&self.dir)
+    }
+}
+
+impl Drop for Page {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+/// A request for the page, as the http layer would pass it on
+pub(crate) fn request() -> Request<Body> {
+    Request::get("internal://internal.com/")
+        .body(Body::empty())
+        .unwrap()
+}
@@ -5,13 +5,63 @@ use std::{
     path::{Path, PathBuf},
 };
 
+/// Environment variable overriding the configured [`ServeMode`]: `prod`, `dev` or `dev-ssr`
+pub const MODE_ENV: &str = "FAIRY_MODE";
+
+/// How the app is served
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ServeMode {
+    /// Rendered from the build output
+    #[default]
+    Prod,
+    /// Rendered in the browser with assets from the vite dev server
+    Dev,
+    /// Rendered on the server with modules from the vite dev server
+    DevSsr,
+}
+
+impl std::str::FromStr for ServeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "prod" | "production" => Ok(ServeMode::Prod),
+            "dev" | "development" => Ok(ServeMode::Dev),
+            "dev-ssr" => Ok(ServeMode::DevSsr),
+            other => Err(format!("unknown mode: {other}")),
+        }
+    }
+}
+
+/// Preamble required by `@vitejs/plugin-react` for fast refresh in dev mode
+pub const REACT_REFRESH_PREAMBLE: &str = r#"<script type="module">
+import RefreshRuntime from "{origin}/@react-refresh";
+RefreshRuntime.injectIntoGlobalHook(window);
+window.$RefreshReg$ = () => {};
+window.$RefreshSig$ = () => (type) => type;
+window.__vite_plugin_react_preamble_installed__ = true;
+</script>"#;
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ViteConfig {
     pub assets: String,
     pub assets_path: String,
     pub base: String,
+    /// Absolute origin assets are served from, eg. `https://cdn.example.com/app/`.
+    /// Defaults to `base`
+    #[serde(default)]
+    pub asset_origin: Option<String>,
     pub client_manifest: String,
+    #[serde(default)]
+    pub mode: ServeMode,
+    #[serde(default)]
+    pub dev_server: DevServer,
+    /// Html added to the head in dev mode, before any scripts.
+    /// `{origin}` is replaced with the dev server origin, see [`REACT_REFRESH_PREAMBLE`]
+    #[serde(default)]
+    pub dev_preamble: Vec<String>,
     pub entries: EntryValue,
     pub port: u16,
     pub root: String,
@@ -29,6 +79,83 @@ impl ViteConfig {
         }
     }
 
+    /// The configured mode, unless overridden by [`MODE_ENV`]
+    pub fn serve_mode(&self) -> ServeMode {
+        let Ok(mode) = std::env::var(MODE_ENV) else {
+            return self.mode;
+        };
+
+        match mode.parse() {
+            Ok(mode) => mode,
+            Err(err) => {
+                tracing::warn!(error = %err, "ignoring {MODE_ENV}");
+                self.mode
+            }
+        }
+    }
+
+    /// Like [`ViteConfig::get_entry`], but fails with [`ViteError::EntryNotFound`]
+    pub fn entry(&self, name: Option<&str>) -> Result<&Entry, ViteError> {
+        self.get_entry(name)
+            .ok_or_else(|| ViteError::EntryNotFound(name.map(ToString::to_string)))
+    }
+
+    /// Origin the vite dev server is reached at from this process
+    pub fn dev_origin(&self) -> String {
+        match &self.dev_server.origin {
+            Some(origin) => origin.trim_end_matches('/').to_string(),
+            None => format!("http://localhost:{}", self.port),
+        }
+    }
+
+    /// Url browsers load dev assets from. With [`DevServer::use_request_host`],
+    /// the host is taken from `host`, usually the `Host` header of the request
+    pub fn dev_public_url(&self, host: Option<&str>) -> String {
+        let url = match &self.dev_server.public_url {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None if self.dev_server.proxy => return String::new(),
+            None => self.dev_origin(),
+        };
+
+        let Some(host) = host.filter(|_| self.dev_server.use_request_host) else {
+            return url;
+        };
+
+        let Ok(uri) = url.parse::<reggie::http::Uri>() else {
+            return url;
+        };
+
+        // The port of the request is the app's, so only the host name is kept
+        let host = match host.rsplit_once(':') {
+            Some((name, port)) if port.parse::<u16>().is_ok() => name,
+            _ => host,
+        };
+
+        let scheme = uri.scheme_str().unwrap_or("http");
+        let path = uri.path().trim_end_matches('/');
+
+        match uri.port_u16() {
+            Some(port) => format!("{scheme}://{host}:{port}{path}"),
+            None => format!("{scheme}://{host}{path}"),
+        }
+    }
+
+    /// Prefix of all asset urls, ending with a slash
+    pub fn public_path(&self) -> String {
+        let path = self.asset_origin.as_deref().unwrap_or(&self.base);
+        with_trailing_slash(path)
+    }
+
+    /// Path the assets directory is served under, `assets_path` relative to `base`.
+    /// When `base` is an absolute url (eg. a cdn), only its path is used
+    pub fn assets_mount(&self) -> String {
+        format!(
+            "{}{}",
+            with_trailing_slash(url_path(&self.base)),
+            self.assets_path.trim_matches('/')
+        )
+    }
+
     pub fn work_dir(&self) -> &Path {
         Path::new(&self.work_dir)
     }
@@ -44,6 +171,56 @@ impl ViteConfig {
     }
 }
 
+/// Where the vite dev server runs
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DevServer {
+    /// Origin used to reach the dev server, eg. `http://vite:5173` inside a container.
+    /// Defaults to `http://localhost:{port}`
+    #[serde(default)]
+    pub origin: Option<String>,
+    /// Url browsers load dev assets from, eg. when behind a reverse proxy or https.
+    /// Defaults to the origin
+    #[serde(default)]
+    pub public_url: Option<String>,
+    /// Replace the host of the public url with the host of the incoming request
+    #[serde(default)]
+    pub use_request_host: bool,
+    /// Dev assets are proxied through this server, so they are loaded from
+    /// the same origin as the page unless a public url is set.
+    /// Requires the `ViteProxyLayer` of `fairy-http` in front of the app, which its builder
+    /// installs unless assets aren't mounted
+    #[serde(default)]
+    pub proxy: bool,
+    /// Milliseconds to wait for a server module in `dev-ssr` mode before the import fails.
+    /// Defaults to 10 seconds
+    #[serde(default)]
+    pub module_timeout: Option<u64>,
+}
+
+/// Normalizes `path` to end with a slash. Relative bases (`./`) are served from the root
+fn with_trailing_slash(path: &str) -> String {
+    match path {
+        "" | "." | "./" => "/".to_string(),
+        path if path.ends_with('/') => path.to_string(),
+        path => format!("{path}/"),
+    }
+}
+
+/// The path of `url`, which may be absolute (`https://cdn/app/`), protocol relative
+/// (`//cdn/app/`) or a plain path
+fn url_path(url: &str) -> &str {
+    let Some(rest) = url
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .or_else(|| url.strip_prefix("//"))
+    else {
+        return url;
+    };
+
+    rest.find('/').map(|idx| &rest[idx..]).unwrap_or("/")
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(untagged)]
 pub enum EntryValue {
@@ -62,3 +239,40 @@ impl ViteConfig {
         load_json(path).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(base: &str) -> ViteConfig {
+        serde_json::from_value(serde_json::json!({
+            "assets": "assets",
+            "assetsPath": "assets",
+            "base": base,
+            "clientManifest": ".vite/manifest.json",
+            "entries": { "client": "src/entry-client.tsx", "server": "src/entry-server.tsx" },
+            "port": 5173,
+            "root": "dist",
+            "serverManifest": ".vite/manifest.json",
+            "ssrManifest": ".vite/ssr-manifest.json",
+            "workDir": ".",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn assets_mount_is_a_path() {
+        assert_eq!(config("/").assets_mount(), "/assets");
+        assert_eq!(config("./").assets_mount(), "/assets");
+        assert_eq!(config("/app").assets_mount(), "/app/assets");
+        assert_eq!(
+            config("https://cdn.example.com/app/").assets_mount(),
+            "/app/assets"
+        );
+        assert_eq!(config("https://cdn.example.com").assets_mount(), "/assets");
+        assert_eq!(
+            config("//cdn.example.com/app/").assets_mount(),
+            "/app/assets"
+        );
+    }
+}
@@ -1,3 +1,7 @@
+use std::path::PathBuf;
+
+use crate::ValidationReport;
+
 #[derive(Debug, thiserror::Error)]
 pub enum ViteError {
     #[error("render error: {0}")]
@@ -9,4 +13,32 @@ pub enum ViteError {
     },
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
+    #[error("entry not found: {}", .0.as_deref().unwrap_or("<default>"))]
+    EntryNotFound(Option<String>),
+    #[error("entry is not an entrypoint: {0}")]
+    NotAnEntrypoint(String),
+    #[error("client entry not found in manifest: {0}")]
+    ClientEntryMissing(String),
+    #[error("could not read {}: {error}", path.display())]
+    File {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    #[error("server module {path} could not be loaded: {error}")]
+    Module {
+        path: String,
+        error: Box<dyn std::error::Error + Send + Sync>,
+    },
+    #[error("{0}")]
+    Invalid(ValidationReport),
+    #[error("invalid dev server origin: {0}")]
+    DevOrigin(String),
+    #[error("no http client configured")]
+    MissingHttpClient,
+    #[error("could not create vm: {0}")]
+    Vm(Box<dyn std::error::Error + Send + Sync>),
+    #[error("invalid route path: {0}")]
+    InvalidRoute(String),
+    #[error("invalid csrf configuration: {0}")]
+    InvalidCsrf(String),
 }
@@ -1,11 +1,14 @@
-use std::{collections::hash_map::Keys, sync::Arc};
+use std::{collections::hash_map::Keys, sync::Arc, time::Duration};
 
-use crate::{config::ViteConfig, vite::Vite, Entry, EntryValue, FairyResult, ViteError};
+use crate::{
+    config::ViteConfig, validate, vite::Vite, vite_resolver::server_module, Entry, EntryValue,
+    FairyResult, ServeMode, ValidationReport, ViteError,
+};
 use fairy_render::{
-    quick::{Quick, QuickFactory},
-    RendererFactory,
+    quick::{DevServerLoader, Isolation, Quick, QuickFactory},
+    ActionPayload, RendererFactory,
 };
-use reggie::{factory_arc, Body, HttpClient, HttpClientFactory, Request};
+use reggie::{factory_arc, Body, HttpClient, HttpClientFactory, Request, SharedClientFactory};
 
 pub struct Fairy {
     pub config: ViteConfig,
@@ -21,11 +24,76 @@ impl Fairy {
         for<'b> <T::Client<Body> as HttpClient<Body>>::Future<'b>: Send,
         <T::Client<Body> as HttpClient<Body>>::Body: Into<reggie::Body>,
     {
-        let factory = QuickFactory::default().search_path(config.root());
+        Fairy::with_factory(config, http, QuickFactory::default()).await
+    }
 
-        let vm = factory.create(factory_arc(http)).await.unwrap();
+    /// Creates a [`Fairy`] in the mode selected by [`ViteConfig::serve_mode`]
+    pub async fn from_config<T>(config: ViteConfig, http: T) -> Result<Fairy, ViteError>
+    where
+        T: HttpClientFactory + Send + Sync + 'static,
+        T::Client<Body>: Send + Sync + 'static,
+        for<'b> <T::Client<Body> as HttpClient<Body>>::Future<'b>: Send,
+        <T::Client<Body> as HttpClient<Body>>::Body: Into<reggie::Body>,
+    {
+        let mode = config.serve_mode();
+        Fairy::with_client(config, factory_arc(http), QuickFactory::default(), mode).await
+    }
 
-        let vite = Vite::new(&config, false).await?;
+    /// Like [`Fairy::new`], using `factory` to configure the vm.
+    /// The build root is added as a search path
+    pub async fn with_factory<T: HttpClientFactory>(
+        config: ViteConfig,
+        http: T,
+        factory: QuickFactory,
+    ) -> Result<Fairy, ViteError>
+    where
+        T: HttpClientFactory + Send + Sync + 'static,
+        T::Client<Body>: Send + Sync + 'static,
+        for<'b> <T::Client<Body> as HttpClient<Body>>::Future<'b>: Send,
+        <T::Client<Body> as HttpClient<Body>>::Body: Into<reggie::Body>,
+    {
+        Fairy::with_client(config, factory_arc(http), factory, ServeMode::Prod).await
+    }
+
+    /// Dev mode rendering on the server. Modules are loaded from the vite dev server
+    /// with `http`, which is also used for fetches made while rendering.
+    /// Every render gets a fresh vm so changes are picked up
+    pub async fn dev_ssr<T>(config: ViteConfig, http: T) -> Result<Fairy, ViteError>
+    where
+        T: HttpClientFactory + Send + Sync + 'static,
+        T::Client<Body>: Send + Sync + 'static,
+        for<'b> <T::Client<Body> as HttpClient<Body>>::Future<'b>: Send,
+        <T::Client<Body> as HttpClient<Body>>::Body: Into<reggie::Body>,
+    {
+        let factory = QuickFactory::default();
+        Fairy::with_client(config, factory_arc(http), factory, ServeMode::DevSsr).await
+    }
+
+    /// Creates a [`Fairy`] in `mode`, using `factory` to configure the vm
+    pub async fn with_client(
+        config: ViteConfig,
+        http: SharedClientFactory,
+        factory: QuickFactory,
+        mode: ServeMode,
+    ) -> Result<Fairy, ViteError> {
+        let (vite, factory) = match mode {
+            ServeMode::Dev => return Fairy::dev(config),
+            ServeMode::Prod => (
+                Vite::new(&config, false).await?,
+                factory.search_path(config.root()),
+            ),
+            ServeMode::DevSsr => (
+                Vite::dev_ssr(&config),
+                factory
+                    .isolation(Isolation::PerRender)
+                    .dev_server(dev_loader(&config, http.clone())),
+            ),
+        };
+
+        let vm = factory
+            .create(http)
+            .await
+            .map_err(|err| ViteError::Vm(Box::new(err)))?;
 
         Ok(Fairy {
             config,
@@ -53,18 +121,90 @@ impl Fairy {
         &self.config
     }
 
-    pub fn create_renderer<'a>(&self, entry: impl Into<Option<&'a str>>) -> FairyRenderer {
-        let entry = entry.into();
+    /// Whether assets are served by the vite dev server
+    pub fn is_dev(&self) -> bool {
+        self.vite.resolver().is_none()
+    }
+
+    /// Validates the build output against the config, collecting every problem found.
+    ///
+    /// In production this also imports each server entry in a vm
+    /// and checks that the assets directory is readable
+    pub async fn validate(&self) -> ValidationReport {
+        let mut report = self.vite.check().await;
 
-        let Some(_) = self.config.get_entry(entry) else {
-            panic!("entry not found: {entry:?}");
+        let (Some(vm), Some(resolver)) = (&self.vm, self.vite.resolver()) else {
+            return report;
         };
 
-        FairyRenderer {
+        for (_, entry) in validate::entries(&self.config) {
+            let Ok((server, _)) = resolver.lookup(&entry.clone().into()) else {
+                continue;
+            };
+
+            let path = server_module(server);
+            if let Err(err) = vm.check(path.as_str().into()).await {
+                report.push(ViteError::Module {
+                    path,
+                    error: Box::new(err),
+                });
+            }
+        }
+
+        let assets = self.config.assets();
+        if let Err(error) = tokio::fs::read_dir(&assets).await {
+            report.push(ViteError::File {
+                path: assets,
+                error,
+            });
+        }
+
+        report
+    }
+
+    /// Paths exported as `routes` by the server module of `entry`.
+    ///
+    /// Returns `None` when the module has no `routes` export, or in [`ServeMode::Dev`]
+    /// where no server module is loaded
+    pub async fn routes<'a>(
+        &self,
+        entry: impl Into<Option<&'a str>>,
+    ) -> Result<Option<Vec<String>>, ViteError> {
+        let (Some(vm), Some(path)) = (&self.vm, self.vite.server_module(entry.into())?) else {
+            return Ok(None);
+        };
+
+        vm.routes(path.clone())
+            .await
+            .map_err(|err| ViteError::Module {
+                path: path.into_string(),
+                error: Box::new(err),
+            })
+    }
+
+    pub fn create_renderer<'a>(
+        &self,
+        entry: impl Into<Option<&'a str>>,
+    ) -> Result<FairyRenderer, ViteError> {
+        let entry = entry.into();
+
+        self.vite.check_entry(entry)?;
+
+        Ok(FairyRenderer {
             vite: self.vite.clone(),
             vm: self.vm.clone(),
             entry: entry.map(|m| m.to_string()),
-        }
+        })
+    }
+}
+
+/// Loader for server modules of the dev server, with the configured timeout
+fn dev_loader(config: &ViteConfig, http: SharedClientFactory) -> DevServerLoader {
+    let loader = DevServerLoader::new(config.dev_origin(), http);
+
+    match config.dev_server.module_timeout {
+        Some(timeout) => loader.timeout(Duration::from_millis(timeout)),
+        None => loader,
     }
 }
 
@@ -76,9 +216,33 @@ pub struct FairyRenderer {
 }
 
 impl FairyRenderer {
+    /// Render this entry in a fresh context every time, trading throughput
+    /// for isolation between requests
+    pub fn isolated(mut self) -> Self {
+        self.vm = self
+            .vm
+            .map(|vm| Arc::new(vm.with_isolation(Isolation::PerRender)));
+        self
+    }
+
     pub async fn render<B: Into<Body>>(&self, req: Request<B>) -> Result<FairyResult, ViteError> {
         self.vite
             .render(self.entry.as_ref().map(|m| m.as_str()), req, &self.vm)
             .await
     }
+
+    pub async fn action<B: Into<Body>>(
+        &self,
+        req: Request<B>,
+        payload: ActionPayload,
+    ) -> Result<FairyResult, ViteError> {
+        self.vite
+            .action(
+                self.entry.as_ref().map(|m| m.as_str()),
+                req,
+                payload,
+                &self.vm,
+            )
+            .await
+    }
 }
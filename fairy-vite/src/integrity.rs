@@ -0,0 +1,114 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::Path,
+};
+
+use base64::Engine;
+use sha2::{Digest, Sha384};
+
+use crate::{vite_resolver::asset_url, Asset, Manifest, SSRManifest};
+
+/// Subresource integrity values of client files, keyed by their url
+#[derive(Debug, Default)]
+pub(crate) struct Integrity {
+    digests: HashMap<String, String>,
+}
+
+impl Integrity {
+    /// Computes SHA-384 digests of every file referenced by the client manifests.
+    ///
+    /// Files are read from the `assets` directory of `client_root`, where they are served from.
+    /// Files outside of it, or which cannot be read, are skipped
+    pub(crate) async fn compute(
+        client_root: &Path,
+        assets: &str,
+        public_path: &str,
+        manifest: &Manifest,
+        ssr_manifest: &SSRManifest,
+    ) -> Integrity {
+        let files = manifest
+            .values()
+            .flat_map(|entry| {
+                std::iter::once(&entry.file)
+                    .chain(&entry.css)
+                    .chain(&entry.assets)
+            })
+            .chain(ssr_manifest.values().flatten())
+            .map(|file| file.trim_start_matches('/'))
+            .collect::<BTreeSet<_>>();
+
+        let assets = assets.trim_matches('/');
+        let assets_dir = client_root.join(assets);
+
+        let mut digests = HashMap::with_capacity(files.len());
+
+        for file in files {
+            let Some(path) = file
+                .strip_prefix(assets)
+                .and_then(|path| path.strip_prefix('/'))
+            else {
+                tracing::debug!(file = %file, "asset is not served from {assets}, skipping integrity");
+                continue;
+            };
+
+            let content = match tokio::fs::read(assets_dir.join(path)).await {
+                Ok(content) => content,
+                Err(err) => {
+                    tracing::warn!(file = %file, error = %err, "could not read asset for integrity");
+                    continue;
+                }
+            };
+
+            let digest = base64::engine::general_purpose::STANDARD.encode(Sha384::digest(&content));
+            digests.insert(asset_url(public_path, file), format!("sha384-{digest}"));
+        }
+
+        Integrity { digests }
+    }
+
+    /// Sets the integrity of `asset`. Integrity checks of cross origin requests
+    /// require cors, so the asset is marked `crossorigin` as well
+    pub(crate) fn apply(&self, mut asset: Asset) -> Asset {
+        if let Some(digest) = self.digests.get(&asset.file) {
+            asset.integrity = Some(digest.clone());
+            asset
+                .crossorigin
+                .get_or_insert(crate::CrossOrigin::Anonymous);
+        }
+        asset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn digests_served_assets() {
+        let root = std::env::temp_dir().join(format!("fairy-integrity-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("static")).unwrap();
+        std::fs::write(root.join("static/app.js"), "console.log(\"hello\");\n").unwrap();
+        std::fs::write(root.join("favicon.ico"), "icon").unwrap();
+
+        let manifest: Manifest = serde_json::from_value(serde_json::json!({
+            "src/app.js": { "file": "static/app.js", "isEntry": true, "assets": ["favicon.ico"] }
+        }))
+        .unwrap();
+
+        let integrity =
+            Integrity::compute(&root, "static", "/app/", &manifest, &SSRManifest::default()).await;
+
+        std::fs::remove_dir_all(&root).ok();
+
+        let asset = integrity.apply(Asset::from_path("/app/static/app.js"));
+        assert_eq!(
+            asset.integrity.as_deref(),
+            Some("sha384-M5mGpKxRozBpvsX+PXs0ssm1NdoBYDPN4gQsyCwq+RTqmuLt5T6LWQKklQd4sArc")
+        );
+        assert_eq!(asset.crossorigin, Some(crate::CrossOrigin::Anonymous));
+
+        // Not part of the served assets directory
+        let asset = integrity.apply(Asset::from_path("/app/favicon.ico"));
+        assert_eq!(asset.integrity, None);
+    }
+}
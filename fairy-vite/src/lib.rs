@@ -1,13 +1,16 @@
 mod error;
+mod integrity;
 mod util;
 
 mod config;
 mod fairy;
 mod result;
+mod validate;
 mod vite;
 mod vite_options;
 mod vite_resolver;
 
 pub use self::{
-    config::*, error::ViteError, fairy::*, result::*, vite::*, vite_options::*, vite_resolver::*,
+    config::*, error::ViteError, fairy::*, result::*, validate::ValidationReport, vite::*,
+    vite_options::*, vite_resolver::*,
 };
@@ -1,14 +1,146 @@
+use std::time::Duration;
+
+use fairy_render::RenderTimings;
+
+use crate::ViteConfig;
+
+/// Type of an asset's file. How it is included in the page is its [`AssetRole`]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum AssetKind {
     Script,
     Styling,
+    Font,
+    Image,
     Unknown,
 }
 
+impl AssetKind {
+    /// Classifies a file by its extension
+    pub fn from_path(path: &str) -> AssetKind {
+        let ext = path
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_ascii_lowercase())
+            .unwrap_or_default();
+
+        match ext.as_str() {
+            "js" | "mjs" | "cjs" | "jsx" | "ts" | "tsx" => AssetKind::Script,
+            "css" => AssetKind::Styling,
+            "woff" | "woff2" | "ttf" | "otf" | "eot" => AssetKind::Font,
+            "png" | "jpg" | "jpeg" | "gif" | "svg" | "webp" | "avif" | "ico" | "bmp" => {
+                AssetKind::Image
+            }
+            _ => AssetKind::Unknown,
+        }
+    }
+
+    /// Value for the `as` attribute of `<link rel="preload">`
+    pub fn as_type(&self) -> Option<&'static str> {
+        match self {
+            AssetKind::Script => Some("script"),
+            AssetKind::Styling => Some("style"),
+            AssetKind::Font => Some("font"),
+            AssetKind::Image => Some("image"),
+            AssetKind::Unknown => None,
+        }
+    }
+}
+
+/// How an asset should be included in the page
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum AssetRole {
+    /// Executed on load, eg. `<script type="module">`
+    #[default]
+    Entry,
+    /// Fetched ahead of time but not executed, eg. `<link rel="modulepreload">`
+    /// for scripts and `<link rel="preload">` for everything else
+    Preload,
+    /// Applied to the page with `<link rel="stylesheet">`
+    Stylesheet,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum CrossOrigin {
+    Anonymous,
+    UseCredentials,
+}
+
+impl CrossOrigin {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CrossOrigin::Anonymous => "anonymous",
+            CrossOrigin::UseCredentials => "use-credentials",
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct Asset {
     pub file: String,
     pub kind: AssetKind,
+    #[serde(default)]
+    pub role: AssetRole,
+    #[serde(default)]
+    pub crossorigin: Option<CrossOrigin>,
+    #[serde(default)]
+    pub integrity: Option<String>,
+}
+
+impl Asset {
+    pub fn new(file: impl Into<String>, kind: AssetKind, role: AssetRole) -> Asset {
+        Asset {
+            file: file.into(),
+            kind,
+            role,
+            crossorigin: None,
+            integrity: None,
+        }
+    }
+
+    /// A script executed on load
+    pub fn entry(file: impl Into<String>) -> Asset {
+        Asset::new(file, AssetKind::Script, AssetRole::Entry)
+    }
+
+    /// A file fetched ahead of time, classified by extension.
+    /// Fonts are always fetched in cors mode, so they are marked `crossorigin`
+    pub fn preload(file: impl Into<String>) -> Asset {
+        let file = file.into();
+        let kind = AssetKind::from_path(&file);
+        let asset = Asset::new(file, kind, AssetRole::Preload);
+        if kind == AssetKind::Font {
+            asset.crossorigin(CrossOrigin::Anonymous)
+        } else {
+            asset
+        }
+    }
+
+    pub fn stylesheet(file: impl Into<String>) -> Asset {
+        Asset::new(file, AssetKind::Styling, AssetRole::Stylesheet)
+    }
+
+    /// Classifies `file` by extension, stylesheets are applied and everything else preloaded
+    pub fn from_path(file: impl Into<String>) -> Asset {
+        let file = file.into();
+        match AssetKind::from_path(&file) {
+            AssetKind::Styling => Asset::stylesheet(file),
+            _ => Asset::preload(file),
+        }
+    }
+
+    pub fn crossorigin(mut self, crossorigin: CrossOrigin) -> Self {
+        self.crossorigin = Some(crossorigin);
+        self
+    }
+
+    pub fn integrity(mut self, integrity: impl Into<String>) -> Self {
+        self.integrity = Some(integrity.into());
+        self
+    }
+
+    /// Value for the `as` attribute when preloading
+    pub fn as_type(&self) -> Option<&'static str> {
+        self.kind.as_type()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
@@ -16,4 +148,40 @@ pub struct FairyResult {
     pub content: Vec<u8>,
     pub assets: Vec<Asset>,
     pub head: Vec<String>,
+    #[serde(default)]
+    pub redirect: Option<String>,
+    #[serde(skip)]
+    pub timings: Timings,
+}
+
+impl FairyResult {
+    /// Result for dev mode without server rendering: framework preambles,
+    /// the vite client for hot module replacement and the client entry.
+    ///
+    /// `host` is the `Host` header of the request, see [`ViteConfig::dev_public_url`]
+    pub fn dev(config: &ViteConfig, client: &str, host: Option<&str>) -> FairyResult {
+        let origin = config.dev_public_url(host);
+
+        FairyResult {
+            head: config
+                .dev_preamble
+                .iter()
+                .map(|head| head.replace("{origin}", &origin))
+                .collect(),
+            assets: vec![
+                Asset::entry(format!("{origin}/@vite/client")),
+                Asset::entry(format!("{origin}/{}", client.trim_start_matches('/'))),
+            ],
+            content: Vec::new(),
+            redirect: None,
+            timings: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timings {
+    pub render: RenderTimings,
+    /// Resolving assets from the manifests
+    pub assets: Duration,
 }
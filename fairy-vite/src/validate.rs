@@ -0,0 +1,67 @@
+use core::fmt;
+use std::path::Path;
+
+use crate::{Entry, EntryValue, ViteConfig, ViteError};
+
+/// Problems found while validating a build against its [`ViteConfig`]
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub problems: Vec<ViteError>,
+}
+
+impl ValidationReport {
+    pub fn push(&mut self, problem: ViteError) {
+        self.problems.push(problem);
+    }
+
+    pub fn extend(&mut self, other: ValidationReport) {
+        self.problems.extend(other.problems);
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Fails with [`ViteError::Invalid`] if any problems were found
+    pub fn into_result(self) -> Result<(), ViteError> {
+        if self.is_ok() {
+            Ok(())
+        } else {
+            Err(ViteError::Invalid(self))
+        }
+    }
+
+    pub(crate) async fn check_file(&mut self, path: &Path) {
+        if let Err(error) = tokio::fs::metadata(path).await {
+            self.push(ViteError::File {
+                path: path.to_path_buf(),
+                error,
+            });
+        }
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} problem(s) found in build output",
+            self.problems.len()
+        )?;
+        for problem in &self.problems {
+            write!(f, "\n  - {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+/// All configured entries along with the name they are looked up by
+pub(crate) fn entries(config: &ViteConfig) -> Vec<(Option<&str>, &Entry)> {
+    match &config.entries {
+        EntryValue::Entry(entry) => vec![(None, entry)],
+        EntryValue::Many(map) => map
+            .iter()
+            .map(|(name, entry)| (Some(name.as_str()), entry))
+            .collect(),
+    }
+}
@@ -1,17 +1,24 @@
-use fairy_render::Renderer;
-use reggie::{http::Request, Body};
+use fairy_render::{ActionPayload, RenderResult, Renderer};
+use reggie::{
+    http::{header, Request},
+    Body,
+};
+use relative_path::RelativePathBuf;
 
 use crate::{
     error::ViteError,
-    result::{Asset, AssetKind, FairyResult},
+    result::FairyResult,
     vite_options::ViteOptions,
-    vite_resolver::ViteResolver,
-    ViteConfig,
+    vite_resolver::{server_module, ViteResolver},
+    ValidationReport, ViteConfig,
 };
 
 enum Mode {
     Prod(ViteResolver),
+    /// Only the client entry is served, rendering happens in the browser
     Dev,
+    /// The server entry is rendered with modules loaded from the dev server
+    DevSsr,
 }
 
 pub struct Vite {
@@ -24,7 +31,11 @@ impl Vite {
         let vite = if dev {
             Self::dev(config)
         } else {
-            let opts = ViteOptions::new(config.root()).client_manifest(&config.client_manifest);
+            let public_path = config.public_path();
+            let opts = ViteOptions::new(config.root())
+                .client_manifest(&config.client_manifest)
+                .public_path(&public_path)
+                .assets(&config.assets);
             let resolver = opts.build().await?;
 
             Vite {
@@ -43,6 +54,16 @@ impl Vite {
         }
     }
 
+    /// Dev mode which renders on the server. The renderer must load modules
+    /// from the dev server, see [`fairy_render::quick::DevServerLoader`]
+    pub fn dev_ssr(config: &ViteConfig) -> Vite {
+        Vite {
+            mode: Mode::DevSsr,
+            config: config.clone(),
+        }
+    }
+
+    #[tracing::instrument(name = "vite_render", skip_all, fields(entry = entry.unwrap_or("default")))]
     pub async fn render<B: Into<Body>, R>(
         &self,
         entry: Option<&str>,
@@ -53,20 +74,117 @@ impl Vite {
         R: Renderer,
         R::Error: std::error::Error + Send + Sync + 'static,
     {
-        let Some(entry) = self.config.get_entry(entry) else {
-            panic!("entry not found: {entry:?}");
-        };
+        let entry = self.config.entry(entry)?;
+        let host = request_host(&req);
 
         match &self.mode {
-            Mode::Dev => Ok(FairyResult {
-                head: Vec::new(),
-                assets: vec![Asset {
-                    kind: AssetKind::Script,
-                    file: format!("http://localhost:{}/{}", self.config.port, entry.client),
-                }],
-                content: Vec::new(),
-            }),
+            Mode::Dev => Ok(self.dev_result(&entry.client, host.as_deref())),
+            Mode::DevSsr => {
+                let ret = renderer.render(dev_module(&entry.server), req.map(Into::into));
+                self.dev_ssr_result(&entry.client, host.as_deref(), ret.await)
+            }
             Mode::Prod(resolver) => resolver.render(entry.clone(), req, renderer).await,
         }
     }
+
+    #[tracing::instrument(name = "vite_action", skip_all, fields(entry = entry.unwrap_or("default")))]
+    pub async fn action<B: Into<Body>, R>(
+        &self,
+        entry: Option<&str>,
+        req: Request<B>,
+        payload: ActionPayload,
+        renderer: &R,
+    ) -> Result<FairyResult, ViteError>
+    where
+        R: Renderer,
+        R::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let entry = self.config.entry(entry)?;
+        let host = request_host(&req);
+
+        match &self.mode {
+            Mode::Dev => Ok(self.dev_result(&entry.client, host.as_deref())),
+            Mode::DevSsr => {
+                let ret = renderer.action(dev_module(&entry.server), req.map(Into::into), payload);
+                self.dev_ssr_result(&entry.client, host.as_deref(), ret.await)
+            }
+            Mode::Prod(resolver) => resolver.action(entry.clone(), req, payload, renderer).await,
+        }
+    }
+
+    pub fn resolver(&self) -> Option<&ViteResolver> {
+        match &self.mode {
+            Mode::Prod(resolver) => Some(resolver),
+            Mode::Dev | Mode::DevSsr => None,
+        }
+    }
+
+    /// Checks every configured entry. Manifests are only checked in production
+    pub async fn check(&self) -> ValidationReport {
+        match &self.mode {
+            Mode::Prod(resolver) => resolver.check(&self.config).await,
+            Mode::Dev | Mode::DevSsr => ValidationReport::default(),
+        }
+    }
+
+    /// Checks that `entry` is configured and, in production, present in the manifests
+    pub fn check_entry(&self, entry: Option<&str>) -> Result<(), ViteError> {
+        let entry = self.config.entry(entry)?;
+
+        if let Mode::Prod(resolver) = &self.mode {
+            resolver.lookup(&entry.clone().into())?;
+        }
+
+        Ok(())
+    }
+
+    /// Path of the server module rendering `entry`, `None` when rendering happens in the browser
+    pub fn server_module(&self, entry: Option<&str>) -> Result<Option<RelativePathBuf>, ViteError> {
+        let entry = self.config.entry(entry)?;
+
+        match &self.mode {
+            Mode::Prod(resolver) => {
+                let (server, _) = resolver.lookup(&entry.clone().into())?;
+                Ok(Some(server_module(server).into()))
+            }
+            Mode::Dev => Ok(None),
+            Mode::DevSsr => Ok(Some(dev_module(&entry.server))),
+        }
+    }
+
+    fn dev_ssr_result<E>(
+        &self,
+        client: &str,
+        host: Option<&str>,
+        ret: Result<RenderResult, E>,
+    ) -> Result<FairyResult, ViteError>
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let ret = ret.map_err(|err| ViteError::Render(Box::new(err)))?;
+
+        let mut result = self.dev_result(client, host);
+        result.content = ret.content.to_vec();
+        result.head.extend(ret.head);
+        result.redirect = ret.redirect;
+        result.timings.render = ret.timings;
+
+        Ok(result)
+    }
+
+    fn dev_result(&self, client: &str, host: Option<&str>) -> FairyResult {
+        FairyResult::dev(&self.config, client, host)
+    }
+}
+
+fn request_host<B>(req: &Request<B>) -> Option<String> {
+    req.headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .map(ToString::to_string)
+}
+
+/// Dev server path of a source module
+fn dev_module(path: &str) -> RelativePathBuf {
+    format!("/{}", path.trim_start_matches('/')).into()
 }
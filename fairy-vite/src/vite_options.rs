@@ -97,6 +97,8 @@ pub struct ViteOptions<'a> {
     pub(crate) server_manifest: Option<&'a str>,
     pub(crate) client_manifest: Option<&'a str>,
     pub(crate) ssr_manifest: Option<&'a str>,
+    pub(crate) public_path: Option<&'a str>,
+    pub(crate) assets: Option<&'a str>,
 }
 
 impl<'a> ViteOptions<'a> {
@@ -106,6 +108,8 @@ impl<'a> ViteOptions<'a> {
             server_manifest: None,
             client_manifest: None,
             ssr_manifest: None,
+            public_path: None,
+            assets: None,
         }
     }
 
@@ -114,6 +118,24 @@ impl<'a> ViteOptions<'a> {
         self
     }
 
+    /// Prefix of asset urls, eg. `/app/` or `https://cdn.example.com/app/`. Defaults to `/`
+    pub fn public_path(mut self, path: &'a str) -> Self {
+        self.public_path = Some(path);
+        self
+    }
+
+    /// Directory of the client build holding the assets, see [`crate::ViteConfig::assets`].
+    /// Defaults to `assets`
+    pub fn assets(mut self, dir: &'a str) -> Self {
+        self.assets = Some(dir);
+        self
+    }
+
+    /// The assets directory as served, relative to the client build
+    pub(crate) fn get_assets(&self) -> &str {
+        self.assets.unwrap_or("assets")
+    }
+
     pub(crate) fn get_server_manifest(&self) -> PathBuf {
         RelativePath::new(self.server_manifest.unwrap_or("server/.vite/manifest.json"))
             .to_logical_path(&self.path)
@@ -142,8 +164,12 @@ pub struct ManifestEntry {
     pub dynamic_imports: Vec<String>,
     #[serde(default, rename = "isEntry")]
     pub is_entry: bool,
+    #[serde(default, rename = "isDynamicEntry")]
+    pub is_dynamic_entry: bool,
     #[serde(default)]
     pub imports: Vec<String>,
+    #[serde(default)]
+    pub assets: Vec<String>,
     // src: String,
 }
 
@@ -1,11 +1,18 @@
-use std::path::PathBuf;
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    path::PathBuf,
+    time::Instant,
+};
 
-use fairy_render::Renderer;
+use fairy_render::{ActionPayload, RenderResult, Renderer};
 use reggie::{Body, Request};
+use relative_path::RelativePathBuf;
 
 use crate::{
-    util::load_json, Asset, AssetKind, Entry, FairyResult, Manifest, SSRManifest, ViteError,
-    ViteOptions,
+    integrity::Integrity, util::load_json, validate, Asset, AssetKind, AssetRole, Entry,
+    FairyResult, Manifest, ManifestEntry, SSRManifest, Timings, ValidationReport, ViteConfig,
+    ViteError, ViteOptions,
 };
 
 #[derive(Clone, Debug)]
@@ -42,6 +49,13 @@ pub struct ViteResolver {
     ssrmanifest: SSRManifest,
     server_manifest: Manifest,
     client_manifest: Manifest,
+    /// Script, preloads and styles of each client entrypoint
+    client_assets: HashMap<String, Vec<Asset>>,
+    /// Preloads and styles of each dynamically imported module
+    dynamic_assets: HashMap<String, Vec<Asset>>,
+    integrity: Integrity,
+    /// Prefix of asset urls, ending with a slash
+    public_path: String,
     root: PathBuf,
 }
 
@@ -56,86 +70,473 @@ impl ViteResolver {
         R: Renderer,
         R::Error: std::error::Error + Send + Sync + 'static,
     {
-        let vite_entry: ViteEntry = entry.into();
+        self.resolve(entry, move |path| {
+            renderer.render(path, req.map(Into::into))
+        })
+        .await
+    }
+
+    pub async fn action<B: Into<Body>, R>(
+        &self,
+        entry: impl Into<ViteEntry>,
+        req: Request<B>,
+        payload: ActionPayload,
+        renderer: &R,
+    ) -> Result<FairyResult, ViteError>
+    where
+        R: Renderer,
+        R::Error: std::error::Error + Send + Sync + 'static,
+    {
+        self.resolve(entry, move |path| {
+            renderer.action(path, req.map(Into::into), payload)
+        })
+        .await
+    }
+
+    /// Checks that every entry in `config` is present in the manifests
+    /// and that the files they reference exist under the build root
+    pub async fn check(&self, config: &ViteConfig) -> ValidationReport {
+        let mut report = ValidationReport::default();
+
+        for (_, entry) in validate::entries(config) {
+            let (server, client) = match self.lookup(&entry.clone().into()) {
+                Ok(ret) => ret,
+                Err(err) => {
+                    report.push(err);
+                    continue;
+                }
+            };
+
+            report
+                .check_file(&self.root.join("server").join(&server.file))
+                .await;
+
+            if let Some(client) = client {
+                let client_root = self.root.join("client");
+                report.check_file(&client_root.join(&client.file)).await;
+                for css in &client.css {
+                    report.check_file(&client_root.join(css)).await;
+                }
+            }
+        }
+
+        report
+    }
 
-        let Some(entry) = self.server_manifest.get(&vite_entry.server) else {
-            panic!("entry not found: {:?}", vite_entry);
+    /// Finds the server and client manifest entries for `entry`
+    pub fn lookup(
+        &self,
+        entry: &ViteEntry,
+    ) -> Result<(&ManifestEntry, Option<&ManifestEntry>), ViteError> {
+        let Some(server) = self.server_manifest.get(&entry.server) else {
+            return Err(ViteError::EntryNotFound(Some(entry.server.clone())));
         };
 
-        if !entry.is_entry {
-            panic!("entry is not an entrypoint");
+        if !server.is_entry {
+            return Err(ViteError::NotAnEntrypoint(entry.server.clone()));
         }
 
-        let mut assets = Vec::default();
+        let client = match &entry.client {
+            Some(client) => match self.client_manifest.get(client) {
+                Some(client) => Some(client),
+                None => return Err(ViteError::ClientEntryMissing(client.clone())),
+            },
+            None => None,
+        };
 
-        if let Some(client) = vite_entry.client {
-            let Some(client_entry) = self.client_manifest.get(&client) else {
-                panic!("client entry does not exists")
-            };
+        Ok((server, client))
+    }
 
-            assets.push(Asset {
-                file: format!("/{}", client_entry.file),
-                kind: AssetKind::Script,
-            });
+    #[tracing::instrument(
+        name = "vite_resolver_render",
+        skip_all,
+        fields(server = tracing::field::Empty, client = tracing::field::Empty)
+    )]
+    async fn resolve<F, U, E>(
+        &self,
+        entry: impl Into<ViteEntry>,
+        render: F,
+    ) -> Result<FairyResult, ViteError>
+    where
+        F: FnOnce(RelativePathBuf) -> U,
+        U: Future<Output = Result<RenderResult, E>>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let start = Instant::now();
+        let vite_entry: ViteEntry = entry.into();
 
-            for css in &client_entry.css {
-                assets.push(Asset {
-                    file: format!("/{}", css),
-                    kind: AssetKind::Styling,
-                });
-            }
+        let span = tracing::Span::current();
+        span.record("server", vite_entry.server.as_str());
+        if let Some(client) = &vite_entry.client {
+            span.record("client", client.as_str());
         }
 
-        let path = format!("./server/{}", entry.file);
+        let (entry, _) = self.lookup(&vite_entry)?;
+
+        let mut assets = match &vite_entry.client {
+            Some(client) => match self.client_assets.get(client) {
+                Some(assets) => assets.clone(),
+                // Not marked as an entry by vite, so not precomputed
+                None => client_assets(
+                    &self.client_manifest,
+                    &self.public_path,
+                    client,
+                    AssetRole::Entry,
+                )
+                .into_iter()
+                .map(|asset| self.integrity.apply(asset))
+                .collect(),
+            },
+            None => Vec::default(),
+        };
 
-        let result = renderer
-            .render(path.into(), req.map(Into::into))
+        let path = server_module(entry);
+
+        let mut assets_time = start.elapsed();
+
+        let result = render(path.into())
             .await
             .map_err(|err| ViteError::Render(Box::new(err)))?;
 
+        let start = Instant::now();
+
+        if let Some(redirect) = result.redirect {
+            return Ok(FairyResult {
+                content: Vec::new(),
+                head: Vec::new(),
+                assets: Vec::new(),
+                redirect: Some(redirect),
+                timings: Timings {
+                    render: result.timings,
+                    assets: assets_time,
+                },
+            });
+        }
+
+        let mut seen = assets
+            .iter()
+            .map(|asset| asset.file.clone())
+            .collect::<HashSet<_>>();
+
         for file in result.assets {
+            // Lazily loaded modules are preloaded along with their imports and css,
+            // they are executed by the client entry when needed
+            if let Some(chunk) = self.dynamic_assets.get(&file) {
+                for asset in chunk {
+                    if seen.insert(asset.file.clone()) {
+                        assets.push(asset.clone());
+                    }
+                }
+                continue;
+            }
+
             let Some(files) = self.ssrmanifest.get(&file) else {
-                println!("could not find {file} in manifest");
+                tracing::warn!(file = %file, "could not find file in ssr manifest");
                 continue;
             };
 
             for file in files {
-                let file_path = relative_path::RelativePath::new(&file);
-                let kind = match file_path.extension() {
-                    Some("js" | "mjs" | "ts" | "tsx") => AssetKind::Script,
-                    Some("css") => AssetKind::Styling,
-                    _ => AssetKind::Unknown,
-                };
-
-                let file = if !file.starts_with("/") {
-                    format!("/{}", file)
-                } else {
-                    file.clone()
-                };
-
-                assets.push(Asset { file, kind })
+                let file = asset_url(&self.public_path, file);
+
+                if seen.insert(file.clone()) {
+                    assets.push(self.integrity.apply(Asset::from_path(file)))
+                }
             }
         }
 
+        assets_time += start.elapsed();
+
         Ok(FairyResult {
             content: result.content.to_vec(),
             head: result.head,
             assets,
+            redirect: None,
+            timings: Timings {
+                render: result.timings,
+                assets: assets_time,
+            },
         })
     }
 }
 
+/// Collects the assets needed by the client chunk `name`: the chunk itself as `role`,
+/// a modulepreload for every statically imported chunk, fonts and all reachable css.
+///
+/// Imports are walked depth first, so css of shared chunks comes before
+/// the css of the chunks importing them.
+fn client_assets(
+    manifest: &Manifest,
+    public_path: &str,
+    name: &str,
+    role: AssetRole,
+) -> Vec<Asset> {
+    fn walk<'a>(
+        manifest: &'a Manifest,
+        name: &'a str,
+        seen: &mut HashSet<&'a str>,
+        preloads: &mut Vec<&'a str>,
+        css: &mut Vec<&'a str>,
+        fonts: &mut Vec<&'a str>,
+    ) {
+        if !seen.insert(name) {
+            return;
+        }
+
+        let Some(entry) = manifest.get(name) else {
+            return;
+        };
+
+        for import in &entry.imports {
+            if let Some(chunk) = manifest.get(import) {
+                if !seen.contains(import.as_str()) {
+                    preloads.push(&chunk.file);
+                }
+            }
+            walk(manifest, import, seen, preloads, css, fonts);
+        }
+
+        for file in &entry.css {
+            if !css.contains(&file.as_str()) {
+                css.push(file);
+            }
+        }
+
+        for file in &entry.assets {
+            if AssetKind::from_path(file) == AssetKind::Font && !fonts.contains(&file.as_str()) {
+                fonts.push(file);
+            }
+        }
+    }
+
+    let Some(entry) = manifest.get(name) else {
+        return Vec::new();
+    };
+
+    let mut seen = HashSet::new();
+    let mut preloads = Vec::new();
+    let mut css = Vec::new();
+    let mut fonts = Vec::new();
+
+    walk(
+        manifest,
+        name,
+        &mut seen,
+        &mut preloads,
+        &mut css,
+        &mut fonts,
+    );
+
+    let mut assets = vec![Asset::new(
+        asset_url(public_path, &entry.file),
+        AssetKind::Script,
+        role,
+    )];
+
+    assets.extend(
+        preloads
+            .into_iter()
+            .map(|file| Asset::preload(asset_url(public_path, file))),
+    );
+
+    assets.extend(
+        css.into_iter()
+            .map(|file| Asset::stylesheet(asset_url(public_path, file))),
+    );
+
+    assets.extend(
+        fonts
+            .into_iter()
+            .map(|file| Asset::preload(asset_url(public_path, file))),
+    );
+
+    assets
+}
+
+/// Chunks lazily imported from `entries`, directly or through their imports
+/// and other lazily imported chunks
+fn dynamic_imports<'a>(manifest: &'a Manifest, entries: &[&'a str]) -> Vec<&'a str> {
+    let mut seen = HashSet::new();
+    let mut dynamic = Vec::new();
+    let mut stack = entries.to_vec();
+
+    while let Some(name) = stack.pop() {
+        if !seen.insert(name) {
+            continue;
+        }
+
+        let Some(entry) = manifest.get(name) else {
+            continue;
+        };
+
+        stack.extend(entry.imports.iter().map(String::as_str));
+
+        for import in &entry.dynamic_imports {
+            if manifest.contains_key(import) && !dynamic.contains(&import.as_str()) {
+                dynamic.push(import.as_str());
+            }
+            stack.push(import);
+        }
+    }
+
+    dynamic
+}
+
+/// Url of the client file `file`
+pub(crate) fn asset_url(public_path: &str, file: &str) -> String {
+    format!("{public_path}{}", file.trim_start_matches('/'))
+}
+
+/// Path of the server module for `entry`, relative to the build root
+pub(crate) fn server_module(entry: &ManifestEntry) -> String {
+    format!("./server/{}", entry.file)
+}
+
 impl<'a> ViteOptions<'a> {
     pub async fn build(self) -> Result<ViteResolver, ViteError> {
         let client_manifest: Manifest = load_json(&self.get_client_manifest()).await?;
         let server_manifest: Manifest = load_json(&self.get_server_manifest()).await?;
         let ssrmanifest = load_json(&self.get_ssr_manifest()).await?;
 
+        let public_path = match self.public_path {
+            Some(path) if path.ends_with('/') => path.to_string(),
+            Some(path) => format!("{path}/"),
+            None => "/".to_string(),
+        };
+
+        let integrity = Integrity::compute(
+            &self.path.join("client"),
+            self.get_assets(),
+            &public_path,
+            &client_manifest,
+            &ssrmanifest,
+        )
+        .await;
+
+        let precompute = |role: AssetRole, names: Vec<&str>| {
+            names
+                .into_iter()
+                .map(|name| {
+                    let assets = client_assets(&client_manifest, &public_path, name, role)
+                        .into_iter()
+                        .map(|asset| integrity.apply(asset))
+                        .collect();
+                    (name.to_string(), assets)
+                })
+                .collect::<HashMap<_, _>>()
+        };
+
+        let entries = client_manifest
+            .iter()
+            .filter(|(_, entry)| entry.is_entry)
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+
+        let dynamic = dynamic_imports(&client_manifest, &entries);
+
+        let client_assets = precompute(AssetRole::Entry, entries);
+        let dynamic_assets = precompute(AssetRole::Preload, dynamic);
+
         Ok(ViteResolver {
             ssrmanifest,
             server_manifest,
             client_manifest,
+            client_assets,
+            dynamic_assets,
+            integrity,
+            public_path,
             root: self.path,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dynamic_imports_are_walked_from_entries() {
+        let manifest: Manifest = serde_json::from_value(serde_json::json!({
+            "src/entry-client.tsx": {
+                "file": "assets/entry.js",
+                "isEntry": true,
+                "imports": ["_shared.js"],
+                "dynamicImports": ["src/subpage.tsx"]
+            },
+            "_shared.js": {
+                "file": "assets/shared.js",
+                "dynamicImports": ["src/settings.tsx"]
+            },
+            "src/subpage.tsx": {
+                "file": "assets/subpage.js",
+                "isDynamicEntry": true,
+                "css": ["assets/subpage.css"],
+                "dynamicImports": ["src/nested.tsx"]
+            },
+            "src/settings.tsx": { "file": "assets/settings.js", "isDynamicEntry": true },
+            "src/nested.tsx": { "file": "assets/nested.js", "isDynamicEntry": true },
+            // Emitted, but not reachable from any entry
+            "src/unused.tsx": { "file": "assets/unused.js", "isDynamicEntry": true }
+        }))
+        .unwrap();
+
+        let mut dynamic = dynamic_imports(&manifest, &["src/entry-client.tsx"]);
+        dynamic.sort();
+
+        assert_eq!(
+            dynamic,
+            ["src/nested.tsx", "src/settings.tsx", "src/subpage.tsx"]
+        );
+
+        let assets = client_assets(&manifest, "/", "src/subpage.tsx", AssetRole::Preload);
+        let files = assets
+            .iter()
+            .map(|asset| asset.file.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(files, ["/assets/subpage.js", "/assets/subpage.css"]);
+        assert_eq!(assets[0].role, AssetRole::Preload);
+    }
+
+    #[test]
+    fn client_assets_follow_import_chains_once() {
+        let manifest: Manifest = serde_json::from_value(serde_json::json!({
+            "src/entry-client.tsx": {
+                "file": "assets/entry.js",
+                "isEntry": true,
+                "imports": ["_layout.js", "_runtime.js"],
+                "css": ["assets/entry.css", "assets/shared.css"]
+            },
+            "_layout.js": {
+                "file": "assets/layout.js",
+                "imports": ["_runtime.js"],
+                "css": ["assets/shared.css", "assets/layout.css"],
+                "assets": ["assets/inter.woff2"]
+            },
+            "_runtime.js": {
+                "file": "assets/runtime.js",
+                "css": ["assets/shared.css"],
+                "assets": ["assets/inter.woff2", "assets/logo.svg"]
+            }
+        }))
+        .unwrap();
+
+        let assets = client_assets(&manifest, "/", "src/entry-client.tsx", AssetRole::Entry);
+        let files = assets
+            .iter()
+            .map(|asset| asset.file.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            files,
+            [
+                "/assets/entry.js",
+                "/assets/layout.js",
+                "/assets/runtime.js",
+                "/assets/shared.css",
+                "/assets/layout.css",
+                "/assets/entry.css",
+                "/assets/inter.woff2",
+            ]
+        );
+        assert_eq!(files.iter().collect::<HashSet<_>>().len(), files.len());
+    }
+}
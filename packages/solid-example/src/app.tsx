@@ -1,24 +1,31 @@
 import { For, Suspense, createEffect, createResource } from "solid-js";
-import { A, Route, Router } from "@solidjs/router";
+import { A, Router, type RouteDefinition } from "@solidjs/router";
 import { lazy } from "@fairy-render/solid";
 
 const Subpage = lazy(() => import("./subpage.jsx"));
 
+// Shared with the server, which registers these paths as routes
+export const routes: RouteDefinition[] = [
+  { path: "/", component: Index },
+  { path: "/subpage", component: Subpage },
+  { path: "*404", component: NotFound },
+];
+
 export default function App(props: { url?: string }) {
   return (
     <div>
       <h3>Page</h3>
       <Suspense fallback={"loading"}>
-        <Router url={props.url}>
-          <Route path="/" component={Index} />
-          <Route path="/subpage" component={Subpage} />
-          <Route path="*" component={() => <div>NotFound</div>} />
-        </Router>
+        <Router url={props.url}>{routes}</Router>
       </Suspense>
     </div>
   );
 }
 
+function NotFound() {
+  return <div>NotFound</div>;
+}
+
 function Index() {
   const [res] = createResource(() =>
     fetch("https://dummyjson.com/products?limit=5").then((resp) => resp.json())